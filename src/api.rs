@@ -0,0 +1,60 @@
+use crate::domain::{
+    AutosuggestRequest, AutosuggestResponse, Carriers, CreateFlightsRequest, Currencies,
+    FlightsResponse, GeoResponse, IndicativeResponse, IndicativeSearchRequest,
+    ItineraryRefreshRequest, ItineraryRefreshResponse, Locales, Markets, NearestCulture,
+};
+use crate::error::SkyscannerError;
+
+/// The subset of the Skyscanner partners API the rest of the crate depends on.
+///
+/// `Services` talks to the real API, `FakeApi` serves canned responses for tests.
+#[async_trait::async_trait]
+pub trait SkyscannerApi: Clone + Send + Sync + Unpin + 'static {
+    async fn get_markets(&self, locale: &str) -> Result<Markets, SkyscannerError>;
+
+    async fn get_locales(&self) -> Result<Locales, SkyscannerError>;
+
+    async fn get_currencies(&self) -> Result<Currencies, SkyscannerError>;
+
+    /// The culture of `ip_address`, or of the caller's address when it's `None`.
+    async fn get_nearest_culture(
+        &self,
+        ip_address: Option<&str>,
+    ) -> Result<NearestCulture, SkyscannerError>;
+
+    async fn create_a_request_to_find_flights(
+        &self,
+        q: &CreateFlightsRequest,
+    ) -> Result<FlightsResponse, SkyscannerError>;
+
+    async fn poll_a_request_to_find_flights(
+        &self,
+        token: &str,
+    ) -> Result<FlightsResponse, SkyscannerError>;
+
+    /// Starts re-checking the prices of one itinerary of the search `session_token`.
+    async fn create_itinerary_refresh(
+        &self,
+        session_token: &str,
+        q: &ItineraryRefreshRequest,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError>;
+
+    async fn poll_itinerary_refresh(
+        &self,
+        refresh_session_token: &str,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError>;
+
+    async fn search_indicative_prices(
+        &self,
+        q: &IndicativeSearchRequest,
+    ) -> Result<IndicativeResponse, SkyscannerError>;
+
+    async fn autosuggest_flights(
+        &self,
+        q: &AutosuggestRequest,
+    ) -> Result<AutosuggestResponse, SkyscannerError>;
+
+    async fn get_flight_locations(&self, locale: &str) -> Result<GeoResponse, SkyscannerError>;
+
+    async fn get_carriers(&self) -> Result<Carriers, SkyscannerError>;
+}
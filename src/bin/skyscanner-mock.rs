@@ -0,0 +1,62 @@
+use std::net::TcpListener;
+
+use clap::value_parser;
+use skyscanner::mock::{serve, MockSettings};
+
+#[tokio::main]
+async fn main() {
+    let matches = clap::Command::new("skyscanner-mock")
+        .about("A local stand-in for the Skyscanner partners API")
+        .arg(
+            clap::Arg::new("port")
+                .long("port")
+                .value_parser(value_parser!(u16))
+                .default_value("8080"),
+        )
+        .arg(
+            clap::Arg::new("polls-until-complete")
+                .long("polls-until-complete")
+                .value_parser(value_parser!(u32))
+                .default_value("2"),
+        )
+        .arg(
+            clap::Arg::new("failure-rate")
+                .long("failure-rate")
+                .help("Share of requests answered with a 429 or a 503, between 0 and 1")
+                .value_parser(value_parser!(f64))
+                .default_value("0"),
+        )
+        .arg(
+            clap::Arg::new("itineraries")
+                .long("itineraries")
+                .value_parser(value_parser!(usize))
+                .default_value("10"),
+        )
+        .arg(
+            clap::Arg::new("api-key")
+                .long("api-key")
+                .help("The only accepted x-api-key, any key is accepted when omitted"),
+        )
+        .get_matches();
+
+    let port = matches.get_one::<u16>("port").expect("Invalid port");
+    let settings = MockSettings {
+        polls_until_complete: *matches
+            .get_one::<u32>("polls-until-complete")
+            .expect("Invalid polls-until-complete"),
+        failure_rate: *matches
+            .get_one::<f64>("failure-rate")
+            .expect("Invalid failure-rate"),
+        itineraries: *matches
+            .get_one::<usize>("itineraries")
+            .expect("Invalid itineraries"),
+        api_key: matches.get_one::<String>("api-key").cloned(),
+    };
+
+    let listener =
+        TcpListener::bind(("127.0.0.1", *port)).expect("Can't bind the mock server port");
+    println!("Listening on http://127.0.0.1:{port}/apiservices/v3/");
+    serve(listener, settings)
+        .await
+        .expect("The mock server stopped");
+}
@@ -0,0 +1,196 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::configuration::CacheSettings;
+use crate::error::SkyscannerError;
+
+/// Keeps reference data, e.g. carriers, as JSON files so it's fetched once per TTL.
+#[derive(Clone)]
+pub struct ReferenceCache {
+    // Nothing is cached without a directory.
+    directory: Option<PathBuf>,
+    ttl: Duration,
+}
+
+impl ReferenceCache {
+    /// Keeps the entries of every API apart, so e.g. the data of `skyscanner-mock` is never
+    /// served to a search against the real API.
+    pub fn new(settings: &CacheSettings, base_url: &str) -> Self {
+        let namespace = fnv1a(base_url.trim_end_matches('/').as_bytes());
+        Self {
+            directory: Some(PathBuf::from(&settings.directory).join(format!("{namespace:016x}"))),
+            ttl: settings.ttl(),
+        }
+    }
+
+    /// Fetches every time and keeps nothing, e.g. when replaying a cassette.
+    pub fn disabled() -> Self {
+        Self {
+            directory: None,
+            ttl: Duration::ZERO,
+        }
+    }
+
+    /// Returns the cached `name` while it's fresh, otherwise fetches and caches it again.
+    ///
+    /// When the fetch fails an expired copy is still better than nothing, so it's served
+    /// instead of the error if there is one.
+    pub async fn get_or_fetch<T, F, Fut>(&self, name: &str, fetch: F) -> Result<T, SkyscannerError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, SkyscannerError>>,
+    {
+        if let Some(value) = self.load(name, false) {
+            return Ok(value);
+        }
+
+        match fetch().await {
+            Ok(value) => {
+                if let Err(e) = self.store(name, &value) {
+                    eprintln!("{e}");
+                }
+                Ok(value)
+            }
+            Err(e) => self.load(name, true).ok_or(e),
+        }
+    }
+
+    /// Reads `name` from the cache, expired entries only when `stale` is set.
+    pub fn load<T: DeserializeOwned>(&self, name: &str, stale: bool) -> Option<T> {
+        let path = self.path(name)?;
+        let age = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if !stale && age >= self.ttl {
+            return None;
+        }
+
+        let content = std::fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn store<T: Serialize>(&self, name: &str, value: &T) -> Result<(), SkyscannerError> {
+        let (Some(directory), Some(path)) = (&self.directory, self.path(name)) else {
+            return Ok(());
+        };
+        let content =
+            serde_json::to_string(value).map_err(|e| SkyscannerError::Cache(e.to_string()))?;
+        std::fs::create_dir_all(directory)
+            .and_then(|_| std::fs::write(&path, content))
+            .map_err(|e| SkyscannerError::Cache(format!("Can't write {}: {e}", path.display())))
+    }
+
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        Some(self.directory.as_ref()?.join(format!("{name}.json")))
+    }
+}
+
+// A hash that stays the same across Rust releases, unlike `DefaultHasher`, so the namespaces
+// outlive a toolchain upgrade.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("cache-{name}-{}", std::process::id()))
+    }
+
+    fn cache(name: &str, ttl_seconds: u64) -> ReferenceCache {
+        cache_of(
+            name,
+            ttl_seconds,
+            "https://partners.api.skyscanner.net/apiservices/v3/",
+        )
+    }
+
+    fn cache_of(name: &str, ttl_seconds: u64, base_url: &str) -> ReferenceCache {
+        ReferenceCache::new(
+            &CacheSettings {
+                directory: directory(name).display().to_string(),
+                ttl_seconds,
+            },
+            base_url,
+        )
+    }
+
+    #[tokio::test]
+    async fn it_works_when_serving_fresh_entries_from_the_cache() {
+        let cache = cache("fresh", 3600);
+        let value = cache
+            .get_or_fetch("numbers", || async { Ok(vec![1, 2, 3]) })
+            .await
+            .unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+
+        let value = cache
+            .get_or_fetch::<Vec<i32>, _, _>("numbers", || async {
+                Err(SkyscannerError::Configuration("not called".to_string()))
+            })
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(directory("fresh")).unwrap();
+        assert_eq!(value, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn it_works_when_the_entry_expired() {
+        let cache = cache("expired", 0);
+        cache.store("numbers", &vec![1]).unwrap();
+
+        let value = cache
+            .get_or_fetch("numbers", || async { Ok(vec![2]) })
+            .await
+            .unwrap();
+        assert_eq!(value, vec![2]);
+
+        let value = cache
+            .get_or_fetch::<Vec<i32>, _, _>("numbers", || async {
+                Err(SkyscannerError::Configuration("offline".to_string()))
+            })
+            .await
+            .unwrap();
+        std::fs::remove_dir_all(directory("expired")).unwrap();
+        assert_eq!(value, vec![2]);
+    }
+
+    #[tokio::test]
+    async fn it_works_when_keeping_the_entries_of_every_api_apart() {
+        let real = cache("apart", 3600);
+        real.store("numbers", &vec![1]).unwrap();
+
+        let mock = cache_of("apart", 3600, "http://127.0.0.1:8080/apiservices/v3/");
+        let value = mock
+            .get_or_fetch("numbers", || async { Ok(vec![2]) })
+            .await
+            .unwrap();
+        assert_eq!(value, vec![2]);
+        assert_eq!(real.load::<Vec<i32>>("numbers", false), Some(vec![1]));
+        assert_eq!(
+            cache("apart", 3600).load::<Vec<i32>>("numbers", false),
+            Some(vec![1])
+        );
+        std::fs::remove_dir_all(directory("apart")).unwrap();
+
+        let disabled = ReferenceCache::disabled();
+        disabled.store("numbers", &vec![3]).unwrap();
+        assert_eq!(disabled.load::<Vec<i32>>("numbers", true), None);
+        let value = disabled
+            .get_or_fetch("numbers", || async { Ok(vec![4]) })
+            .await
+            .unwrap();
+        assert_eq!(value, vec![4]);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reqwest::header::HeaderMap;
+use tokio::io::AsyncWriteExt;
+
+use crate::configuration::{CassetteMode, CassetteSettings};
+use crate::error::SkyscannerError;
+
+/// Value written instead of the API key, which must never end up in a cassette.
+pub const REDACTED: &str = "[REDACTED]";
+
+const API_KEY: &str = "x-api-key";
+
+/// One request sent to the API and the response it got.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Interaction {
+    pub method: String,
+    pub endpoint: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub request_body: Option<serde_json::Value>,
+    pub status: u16,
+    pub response_body: String,
+}
+
+/// The interactions of a cassette file, one JSON object per line in recording order.
+#[derive(Default, Debug)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+/// Records or replays the traffic of `Services`, shared between its clones.
+#[derive(Clone)]
+pub enum Tape {
+    Record(Recorder),
+    Replay(Player),
+}
+
+/// Appends every interaction to the cassette file as soon as it's recorded.
+#[derive(Clone)]
+pub struct Recorder {
+    path: PathBuf,
+    file: Arc<tokio::sync::Mutex<Option<tokio::fs::File>>>,
+}
+
+/// Serves recorded responses keyed by method, endpoint and request body.
+///
+/// Identical requests, e.g. polls of the same session, get their responses in recording
+/// order; the last one keeps being served once the others are used up.
+#[derive(Clone)]
+pub struct Player {
+    interactions: Arc<Mutex<HashMap<String, VecDeque<Interaction>>>>,
+}
+
+impl Tape {
+    pub fn new(settings: &CassetteSettings) -> Result<Self, SkyscannerError> {
+        match settings.mode {
+            CassetteMode::Record => Ok(Self::Record(Recorder::new(&settings.path))),
+            CassetteMode::Replay => Ok(Self::Replay(Player::load(&settings.path)?)),
+        }
+    }
+}
+
+impl Interaction {
+    /// The headers a request was sent with, the API key or any other sensitive value redacted.
+    pub fn request_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if value.is_sensitive() || name.as_str() == API_KEY {
+                    REDACTED.to_string()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.as_str().to_string(), value)
+            })
+            .collect()
+    }
+}
+
+impl Recorder {
+    /// Starts an empty cassette, the file is truncated by the first recorded interaction.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            file: Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Appends the interaction as one line, so an interrupted run keeps everything recorded up
+    /// to that point.
+    pub async fn record(&self, mut interaction: Interaction) -> Result<(), SkyscannerError> {
+        if let Some(value) = interaction.request_headers.get_mut(API_KEY) {
+            *value = REDACTED.to_string();
+        }
+        let mut line = serde_json::to_string(&interaction)
+            .map_err(|e| SkyscannerError::Cassette(e.to_string()))?;
+        line.push('\n');
+
+        let error = |e: std::io::Error| {
+            SkyscannerError::Cassette(format!("Can't write {}: {e}", self.path.display()))
+        };
+        let mut file = self.file.lock().await;
+        if file.is_none() {
+            *file = Some(tokio::fs::File::create(&self.path).await.map_err(error)?);
+        }
+        let file = file.as_mut().unwrap();
+        file.write_all(line.as_bytes()).await.map_err(error)?;
+        file.flush().await.map_err(error)
+    }
+}
+
+impl Player {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SkyscannerError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            SkyscannerError::Cassette(format!("Can't read {}: {e}", path.display()))
+        })?;
+        let interactions = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str::<Interaction>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| SkyscannerError::Cassette(format!("Invalid {}: {e}", path.display())))?;
+        Ok(Self::new(Cassette { interactions }))
+    }
+
+    pub fn new(cassette: Cassette) -> Self {
+        let mut interactions = HashMap::<String, VecDeque<Interaction>>::new();
+        for interaction in cassette.interactions {
+            let key = key(
+                &interaction.method,
+                &interaction.endpoint,
+                interaction.request_body.as_ref(),
+            );
+            interactions.entry(key).or_default().push_back(interaction);
+        }
+        Self {
+            interactions: Arc::new(Mutex::new(interactions)),
+        }
+    }
+
+    pub fn replay(
+        &self,
+        method: &str,
+        endpoint: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Result<Interaction, SkyscannerError> {
+        let mut interactions = self.interactions.lock().unwrap();
+        let queue = interactions
+            .get_mut(&key(method, endpoint, body))
+            .filter(|queue| !queue.is_empty())
+            .ok_or_else(|| {
+                SkyscannerError::Cassette(format!("No recorded response for {method} {endpoint}"))
+            })?;
+
+        if queue.len() > 1 {
+            Ok(queue.pop_front().unwrap())
+        } else {
+            Ok(queue[0].clone())
+        }
+    }
+}
+
+// `serde_json::Value` keeps object keys sorted, so equal bodies always give the same key.
+fn key(method: &str, endpoint: &str, body: Option<&serde_json::Value>) -> String {
+    let body = body.map(|e| e.to_string()).unwrap_or_default();
+    format!("{method} {endpoint} {body}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn interaction(endpoint: &str, body: Option<serde_json::Value>, response: &str) -> Interaction {
+        Interaction {
+            method: "POST".to_string(),
+            endpoint: endpoint.to_string(),
+            request_headers: BTreeMap::from([("x-api-key".to_string(), "secret".to_string())]),
+            request_body: body,
+            status: 200,
+            response_body: response.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_works_when_recording_with_a_redacted_api_key() {
+        let path = std::env::temp_dir().join(format!("cassette-{}.jsonl", std::process::id()));
+        let recorder = Recorder::new(&path);
+        recorder
+            .record(interaction(
+                "flights/live/search/poll/a",
+                None,
+                "first poll",
+            ))
+            .await
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(API_KEY, "secret".parse().unwrap());
+        headers.insert("content-type", "application/json".parse().unwrap());
+        let mut poll = interaction("flights/live/search/poll/a", None, "second poll");
+        poll.request_headers = Interaction::request_headers(&headers);
+        recorder.record(poll).await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let player = Player::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!content.contains("secret"));
+        assert_eq!(content.lines().count(), 2);
+
+        let replayed = player
+            .replay("POST", "flights/live/search/poll/a", None)
+            .unwrap();
+        assert_eq!(replayed.request_headers[API_KEY], REDACTED);
+        let replayed = player
+            .replay("POST", "flights/live/search/poll/a", None)
+            .unwrap();
+        assert_eq!(replayed.response_body, "second poll");
+        assert_eq!(replayed.request_headers[API_KEY], REDACTED);
+        assert_eq!(replayed.request_headers["content-type"], "application/json");
+    }
+
+    #[test]
+    fn it_works_when_replaying_by_endpoint_and_body() {
+        let create = serde_json::json!({ "query": { "market": "TW" } });
+        let player = Player::new(Cassette {
+            interactions: vec![
+                interaction("flights/live/search/create", Some(create.clone()), "create"),
+                interaction("flights/live/search/poll/a", None, "first poll"),
+                interaction("flights/live/search/poll/a", None, "second poll"),
+            ],
+        });
+
+        let replayed = player
+            .replay("POST", "flights/live/search/create", Some(&create))
+            .unwrap();
+        assert_eq!(replayed.response_body, "create");
+
+        let other = serde_json::json!({ "query": { "market": "HK" } });
+        assert!(player
+            .replay("POST", "flights/live/search/create", Some(&other))
+            .is_err());
+
+        let polls = (0..3)
+            .map(|_| {
+                player
+                    .replay("POST", "flights/live/search/poll/a", None)
+                    .unwrap()
+                    .response_body
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(polls, vec!["first poll", "second poll", "second poll"]);
+    }
+}
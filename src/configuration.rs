@@ -1,8 +1,73 @@
+use std::time::Duration;
+
 use secrecy::Secret;
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub api_key: Secret<String>,
+    pub search: SearchSettings,
+    pub retry: RetrySettings,
+    pub rate_limit: RateLimitSettings,
+    pub http: HttpSettings,
+    pub cache: CacheSettings,
+    /// Defaults of `--market`, `--locale` and `--currency`, detected when unset.
+    #[serde(default)]
+    pub culture: CultureSettings,
+    /// Records the API traffic to, or replays it from, a cassette file when set.
+    pub cassette: Option<CassetteSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CultureSettings {
+    pub market: Option<String>,
+    pub locale: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CacheSettings {
+    /// Directory holding the cached reference data, e.g. carriers.
+    pub directory: String,
+    /// How long cached reference data is used before it's fetched again.
+    pub ttl_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CassetteSettings {
+    pub mode: CassetteMode,
+    pub path: String,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct HttpSettings {
+    /// Root of the partners API, e.g. a local mock server or the sandbox environment.
+    pub base_url: String,
+    pub timeout_milliseconds: u64,
+    pub connect_timeout_milliseconds: u64,
+    /// Proxy used for every request, e.g. `http://proxy.corp:3128`.
+    pub proxy: Option<String>,
+    pub user_agent: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SearchSettings {
+    /// How long to wait between two polls of the same live search.
+    pub poll_interval_milliseconds: u64,
+    /// Overall time budget of a live search, measured from its creation.
+    pub deadline_seconds: u64,
+    /// Maximum number of polls sent after the create request.
+    pub max_polls: u32,
+    /// How many live searches may run at the same time.
+    pub max_concurrent_searches: usize,
+    /// Most live searches, one per route and trip, a run may start without `--prescreen`.
+    pub max_trips: usize,
 }
 
 pub enum Environment {
@@ -28,12 +93,80 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(
             configuration_directory.join(environment_filename),
         ))
-        .add_source(config::Environment::with_prefix("APP").prefix_separator("__"))
+        // e.g. `APP__HTTP__BASE_URL=http://localhost:8080` sets `http.base_url`
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("__")
+                .separator("__"),
+        )
         .build()?;
 
     settings.try_deserialize::<Settings>()
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct RetrySettings {
+    /// Total number of attempts per request, including the first one.
+    pub max_attempts: u32,
+    /// Delay after the first failed attempt, doubled after every further one.
+    pub base_delay_milliseconds: u64,
+    /// Upper bound of the exponential backoff, before jitter.
+    pub max_delay_milliseconds: u64,
+    /// A random delay between zero and this value is added to every backoff.
+    pub jitter_milliseconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Sustained request rate shared by every clone of `Services`, zero disables it.
+    pub requests_per_second: f64,
+    /// How many requests may be sent back to back before the rate applies.
+    pub burst: u32,
+    /// Maximum number of requests waiting for a response at the same time.
+    pub max_in_flight: usize,
+}
+
+impl HttpSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+
+    pub fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_milliseconds)
+    }
+}
+
+impl CultureSettings {
+    /// Keeps the values of `self`, taking the missing ones from `other`.
+    pub fn or(self, other: CultureSettings) -> Self {
+        Self {
+            market: self.market.or(other.market),
+            locale: self.locale.or(other.locale),
+            currency: self.currency.or(other.currency),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.market.is_some() && self.locale.is_some() && self.currency.is_some()
+    }
+}
+
+impl CacheSettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+}
+
+impl SearchSettings {
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    pub fn deadline(&self) -> Duration {
+        Duration::from_secs(self.deadline_seconds)
+    }
+}
+
 impl Environment {
     pub fn as_str(&self) -> &'static str {
         match self {
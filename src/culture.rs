@@ -0,0 +1,253 @@
+use crate::api::SkyscannerApi;
+use crate::cache::ReferenceCache;
+use crate::configuration::CultureSettings;
+use crate::domain::{Currency, Locale, Market, NearestCulture};
+use crate::error::SkyscannerError;
+use crate::utils::closest_match;
+
+// Used when the culture can neither be detected nor read from the system locale.
+pub const FALLBACK_LOCALE: &str = "en-GB";
+pub const FALLBACK_MARKET: &str = "UK";
+pub const FALLBACK_CURRENCY: &str = "GBP";
+
+/// The markets, locales and currencies the API accepts, loaded through the reference cache.
+#[derive(Debug, Clone, Default)]
+pub struct Culture {
+    pub markets: Vec<Market>,
+    pub locales: Vec<Locale>,
+    pub currencies: Vec<Currency>,
+}
+
+impl Culture {
+    /// Loads the three lists, market names are in `locale`.
+    pub async fn load<A: SkyscannerApi>(
+        api: &A,
+        cache: &ReferenceCache,
+        locale: &str,
+    ) -> Result<Self, SkyscannerError> {
+        let locales = cache
+            .get_or_fetch("locales", || async { api.get_locales().await })
+            .await?
+            .locales;
+        let currencies = cache
+            .get_or_fetch("currencies", || async { api.get_currencies().await })
+            .await?
+            .currencies;
+
+        // An unknown locale would fail the markets request, the markets are only listed in
+        // one the API knows.
+        let locale = validate("locale", locale, locales.iter().map(|e| e.code.as_str()))
+            .unwrap_or_else(|_| FALLBACK_LOCALE.to_string());
+        let markets = cache
+            .get_or_fetch(&format!("markets-{locale}"), || async {
+                api.get_markets(&locale).await
+            })
+            .await?
+            .markets;
+
+        Ok(Self {
+            markets,
+            locales,
+            currencies,
+        })
+    }
+
+    /// Returns the market code as the API spells it, e.g. `tw` becomes `TW`.
+    pub fn validate_market(&self, input: &str) -> Result<String, SkyscannerError> {
+        validate(
+            "market",
+            input,
+            self.markets.iter().map(|e| e.code.as_str()),
+        )
+    }
+
+    pub fn validate_locale(&self, input: &str) -> Result<String, SkyscannerError> {
+        validate(
+            "locale",
+            input,
+            self.locales.iter().map(|e| e.code.as_str()),
+        )
+    }
+
+    pub fn validate_currency(&self, input: &str) -> Result<String, SkyscannerError> {
+        validate(
+            "currency",
+            input,
+            self.currencies.iter().map(|e| e.code.as_str()),
+        )
+    }
+
+    pub fn currency(&self, code: &str) -> Option<&Currency> {
+        self.currencies.iter().find(|e| e.code == code)
+    }
+
+    /// The currency of a market, as listed by the markets endpoint.
+    pub fn market_currency(&self, market: &str) -> Option<&str> {
+        self.markets
+            .iter()
+            .find(|e| e.code.eq_ignore_ascii_case(market) && !e.currency.is_empty())
+            .map(|e| e.currency.as_str())
+    }
+}
+
+/// Detects the culture of the caller through the nearest culture endpoint, falling back to
+/// the system locale, e.g. when offline.
+///
+/// It isn't cached, the nearest culture follows the caller's IP address around.
+pub async fn detect_culture<A: SkyscannerApi>(api: &A) -> CultureSettings {
+    match api.get_nearest_culture(None).await {
+        Ok(nearest) => nearest.into(),
+        Err(e) => {
+            eprintln!("Can't detect the nearest culture, using the system locale: {e}");
+            std::env::var("LC_ALL")
+                .into_iter()
+                .chain(std::env::var("LC_MESSAGES"))
+                .chain(std::env::var("LANG"))
+                .find(|value| !value.is_empty())
+                .map(|value| system_culture(&value))
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Reads a POSIX locale such as `zh_TW.UTF-8` as the `zh-TW` locale of the `TW` market.
+pub fn system_culture(value: &str) -> CultureSettings {
+    let name = value.split(['.', '@']).next().unwrap_or_default();
+    let Some((language, territory)) = name.split_once('_') else {
+        return CultureSettings::default();
+    };
+    if language.is_empty() || territory.is_empty() {
+        return CultureSettings::default();
+    }
+
+    CultureSettings {
+        market: Some(territory.to_uppercase()),
+        locale: Some(format!("{language}-{}", territory.to_uppercase())),
+        currency: None,
+    }
+}
+
+impl From<NearestCulture> for CultureSettings {
+    fn from(value: NearestCulture) -> Self {
+        Self {
+            market: Some(value.market.code),
+            locale: Some(value.locale.code),
+            currency: Some(value.currency.code),
+        }
+    }
+}
+
+fn validate<'a>(
+    kind: &str,
+    input: &str,
+    codes: impl Iterator<Item = &'a str>,
+) -> Result<String, SkyscannerError> {
+    let codes = codes.collect::<Vec<_>>();
+    if let Some(code) = codes.iter().find(|code| code.eq_ignore_ascii_case(input)) {
+        return Ok(code.to_string());
+    }
+
+    let message = match closest_match(input, &codes) {
+        Some(code) => format!("Unknown {kind} \"{input}\", did you mean \"{code}\"?"),
+        None => format!("Unknown {kind} \"{input}\""),
+    };
+    Err(SkyscannerError::InvalidQuery(message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::CacheSettings;
+    use crate::domain::{Currencies, Locales, Markets};
+    use crate::fake::FakeApi;
+
+    #[tokio::test]
+    async fn it_works_when_validating_culture_codes() {
+        let api = FakeApi::new()
+            .set_markets(Markets {
+                markets: vec![Market {
+                    code: "TW".to_string(),
+                    name: "Taiwan".to_string(),
+                    currency: "TWD".to_string(),
+                }],
+            })
+            .set_locales(Locales {
+                locales: vec![Locale {
+                    code: "zh-TW".to_string(),
+                    name: "Chinese (Traditional)".to_string(),
+                }],
+            })
+            .set_currencies(
+                serde_json::from_value::<Currencies>(serde_json::json!({
+                    "currencies": [{ "code": "TWD", "symbol": "NT$" }]
+                }))
+                .unwrap(),
+            );
+        let directory = std::env::temp_dir().join(format!("culture-{}", std::process::id()));
+        let cache = ReferenceCache::new(
+            &CacheSettings {
+                directory: directory.display().to_string(),
+                ttl_seconds: 0,
+            },
+            "http://localhost/",
+        );
+
+        let culture = Culture::load(&api, &cache, "zh-TW").await.unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(culture.validate_market("tw").unwrap(), "TW");
+        assert_eq!(culture.validate_locale("ZH-tw").unwrap(), "zh-TW");
+        assert_eq!(culture.validate_currency("TWD").unwrap(), "TWD");
+
+        let error = culture.validate_currency("TDW").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid query: Unknown currency \"TDW\", did you mean \"TWD\"?"
+        );
+        let error = culture.validate_market("GB").unwrap_err();
+        assert_eq!(error.to_string(), "Invalid query: Unknown market \"GB\"");
+        assert_eq!(culture.market_currency("tw"), Some("TWD"));
+    }
+
+    #[test]
+    fn it_works_when_reading_the_system_locale() {
+        assert_eq!(
+            system_culture("zh_TW.UTF-8"),
+            CultureSettings {
+                market: Some("TW".to_string()),
+                locale: Some("zh-TW".to_string()),
+                currency: None,
+            }
+        );
+        assert_eq!(
+            system_culture("en_gb@euro").locale.as_deref(),
+            Some("en-GB")
+        );
+        assert_eq!(system_culture("C.UTF-8"), CultureSettings::default());
+        assert_eq!(system_culture("POSIX"), CultureSettings::default());
+    }
+
+    #[tokio::test]
+    async fn it_works_when_detecting_the_nearest_culture() {
+        let nearest = serde_json::from_value::<NearestCulture>(serde_json::json!({
+            "market": { "code": "JP", "name": "Japan", "currency": "JPY" },
+            "locale": { "code": "ja-JP", "name": "Japanese" },
+            "currency": { "code": "JPY", "symbol": "¥" }
+        }))
+        .unwrap();
+        let api = FakeApi::new().set_nearest_culture(nearest);
+
+        let detected = detect_culture(&api).await;
+
+        let chosen = CultureSettings {
+            market: None,
+            locale: Some(FALLBACK_LOCALE.to_string()),
+            currency: None,
+        }
+        .or(detected);
+        assert!(chosen.is_complete());
+        assert_eq!(chosen.market.as_deref(), Some("JP"));
+        assert_eq!(chosen.locale.as_deref(), Some("en-GB"));
+        assert_eq!(chosen.currency.as_deref(), Some("JPY"));
+    }
+}
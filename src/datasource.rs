@@ -1,24 +1,205 @@
-use crate::domain::{CreateFlightsRequest, FlightsResponse, Query, ResponseStatus};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use chrono::NaiveDate;
+
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+
+use crate::api::SkyscannerApi;
+use crate::configuration::SearchSettings;
+use crate::domain::{
+    CreateFlightsRequest, FightResult, FlightsResponse, IndicativeQuery, IndicativeQueryLeg,
+    IndicativeSearchRequest, ItineraryRefreshRequest, Place, Query, RefreshedItinerary,
+    ResponseStatus,
+};
+use crate::error::SkyscannerError;
 use crate::services::Services;
+use crate::utils::prescreen_dates;
 
-pub struct Datasource {
+/// A live search exposed as a stream of the create response followed by every poll response.
+///
+/// Every response passing through the stream is also applied to an accumulated snapshot,
+/// see [`Datasource::snapshot`].
+pub struct Datasource<A: SkyscannerApi = Services> {
+    inner: BoxStream<'static, anyhow::Result<FlightsResponse>>,
+    results: FightResult,
     session_token: Option<String>,
-    services: Services,
+    status: Option<ResponseStatus>,
+    /// The currency of the query, the one prices are read in.
+    currency: String,
+    services: A,
+    settings: SearchSettings,
+}
+
+/// The merged results of a search run to its end.
+#[derive(Debug)]
+pub struct Searched {
+    pub results: FightResult,
+    /// Why the search ended before it completed, e.g. a failed poll, the deadline or
+    /// `max_polls`; `results` then holds whatever was found until then.
+    pub incomplete: Option<anyhow::Error>,
+}
+
+struct Search<A> {
+    session_token: Option<String>,
+    services: A,
     query: Query,
+    settings: SearchSettings,
+    started_at: Option<Instant>,
+    polls: u32,
     completed: bool,
 }
 
-impl Datasource {
-    pub fn new(query: Query, services: Services) -> Self {
-        Self {
+impl<A: SkyscannerApi> Datasource<A> {
+    pub fn new(query: Query, services: A, settings: SearchSettings) -> Self {
+        let currency = query.currency.clone();
+        let search = Search {
             query,
-            services,
+            services: services.clone(),
+            settings: settings.clone(),
             session_token: None,
+            started_at: None,
+            polls: 0,
             completed: false,
+        };
+
+        let inner = futures::stream::unfold(search, |mut search| async move {
+            match search.next().await {
+                Ok(Some(res)) => Some((Ok(res), search)),
+                Ok(None) => None,
+                Err(e) => {
+                    // An error ends the search, the stream yields it and then finishes.
+                    search.completed = true;
+                    Some((Err(e), search))
+                }
+            }
+        })
+        .boxed();
+
+        Self {
+            inner,
+            results: FightResult::default(),
+            session_token: None,
+            status: None,
+            currency,
+            services,
+            settings,
         }
     }
 
-    pub async fn next(&mut self) -> anyhow::Result<Option<FlightsResponse>> {
+    /// The merged results of every response seen so far.
+    pub fn snapshot(&self) -> &FightResult {
+        &self.results
+    }
+
+    /// Runs the search to the end and returns its merged results, also when it ends early.
+    pub async fn search(mut self) -> Searched {
+        let mut incomplete = None;
+        while let Some(res) = self.next().await {
+            if let Err(e) = res {
+                incomplete = Some(e);
+            }
+        }
+        if incomplete.is_none() && self.status != Some(ResponseStatus::ResultStatusComplete) {
+            incomplete = Some(
+                SkyscannerError::SearchIncomplete {
+                    session_token: self.session_token.clone().unwrap_or_default(),
+                }
+                .into(),
+            );
+        }
+
+        Searched {
+            results: self.results,
+            incomplete,
+        }
+    }
+
+    /// Re-checks the prices of one itinerary of this search, e.g. right before booking it.
+    ///
+    /// The refresh is polled like the search itself, within the same poll and deadline limits,
+    /// and its pricing options are compared with the ones in the snapshot.
+    pub async fn refresh(&self, itinerary_id: &str) -> anyhow::Result<RefreshedItinerary> {
+        let Some(session_token) = &self.session_token else {
+            anyhow::bail!("The search hasn't been created yet");
+        };
+
+        let started_at = Instant::now();
+        let deadline = self.settings.deadline();
+        let req = ItineraryRefreshRequest {
+            itinerary_id: itinerary_id.to_string(),
+        };
+        let mut res = self
+            .services
+            .create_itinerary_refresh(session_token, &req)
+            .await?;
+        let mut results = FightResult::default();
+        results.apply(&res.action, res.content.results);
+
+        let mut polls = 0;
+        loop {
+            let elapsed = started_at.elapsed();
+            if is_finished(&res.status) || polls >= self.settings.max_polls || elapsed >= deadline {
+                break;
+            }
+
+            let interval = self.settings.poll_interval().min(deadline - elapsed);
+            tokio::time::sleep(interval).await;
+
+            polls += 1;
+            res = self
+                .services
+                .poll_itinerary_refresh(&res.refresh_session_token)
+                .await?;
+            results.apply(&res.action, res.content.results);
+        }
+
+        let previous = self
+            .results
+            .itineraries
+            .get(itinerary_id)
+            .map(|e| e.pricing_options.as_slice())
+            .unwrap_or_default();
+        let current = results
+            .itineraries
+            .remove(itinerary_id)
+            .map(|e| e.pricing_options)
+            .unwrap_or_default();
+        Ok(RefreshedItinerary::new(
+            itinerary_id,
+            &self.currency,
+            previous,
+            current,
+        ))
+    }
+}
+
+impl<A: SkyscannerApi> Stream for Datasource<A> {
+    type Item = anyhow::Result<FlightsResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(res))) = &polled {
+            self.session_token = Some(res.session_token.clone());
+            self.status = Some(res.status.clone());
+            self.results.apply(&res.action, res.content.results.clone());
+        }
+        polled
+    }
+}
+
+/// Merges many datasources into one stream, running at most `limit` searches at the same time.
+pub fn merge<A: SkyscannerApi>(
+    datasources: impl IntoIterator<Item = Datasource<A>>,
+    limit: usize,
+) -> impl Stream<Item = anyhow::Result<FlightsResponse>> {
+    futures::stream::iter(datasources).flatten_unordered(limit.max(1))
+}
+
+impl<A: SkyscannerApi> Search<A> {
+    async fn next(&mut self) -> anyhow::Result<Option<FlightsResponse>> {
         if self.completed {
             return Ok(None);
         }
@@ -26,20 +207,359 @@ impl Datasource {
             let req = CreateFlightsRequest {
                 query: self.query.clone(),
             };
+            self.started_at = Some(Instant::now());
             let res = self.services.create_a_request_to_find_flights(&req).await?;
-            if let Some(res) = res {
-                self.session_token = Some(res.session_token.clone());
-                self.completed = res.status == ResponseStatus::ResultStatusComplete;
-                Ok(Some(res))
-            } else {
-                Ok(None)
-            }
+            self.session_token = Some(res.session_token.clone());
+            self.completed = is_finished(&res.status);
+            Ok(Some(res))
         } else if let Some(token) = &self.session_token {
+            let elapsed = self.started_at.map(|e| e.elapsed()).unwrap_or_default();
+            let deadline = self.settings.deadline();
+            if self.polls >= self.settings.max_polls || elapsed >= deadline {
+                self.completed = true;
+                return Ok(None);
+            }
+
+            // Never sleep past the deadline, the last poll still gets a chance to run.
+            let interval = self.settings.poll_interval().min(deadline - elapsed);
+            tokio::time::sleep(interval).await;
+
+            self.polls += 1;
             let res = self.services.poll_a_request_to_find_flights(token).await?;
-            self.completed = res.status == ResponseStatus::ResultStatusComplete;
+            self.completed = is_finished(&res.status);
             Ok(Some(res))
         } else {
             unreachable!()
         }
     }
 }
+
+/// Ranks the trips by the cached prices of one indicative search spanning all of them, every
+/// trip is kept when the search fails.
+pub async fn prescreen<A: SkyscannerApi>(
+    services: &A,
+    q: &Query,
+    from: &Place,
+    to: &Place,
+    dates: Vec<(NaiveDate, Option<NaiveDate>)>,
+    limit: usize,
+) -> Vec<(NaiveDate, Option<NaiveDate>)> {
+    let (Some(first), Some(last)) = (
+        dates.iter().map(|(s, _)| *s).min(),
+        dates.iter().map(|(s, e)| e.unwrap_or(*s)).max(),
+    ) else {
+        return dates;
+    };
+
+    let mut query =
+        IndicativeQuery::new(q.market.clone(), q.locale.clone(), q.currency.clone()).set_query_leg(
+            IndicativeQueryLeg::month_range(from.clone(), to.clone(), first.into(), last.into()),
+        );
+    if dates.iter().any(|(_, e)| e.is_some()) {
+        query = query.set_query_leg(IndicativeQueryLeg::month_range(
+            to.clone(),
+            from.clone(),
+            first.into(),
+            last.into(),
+        ));
+    }
+
+    match services
+        .search_indicative_prices(&IndicativeSearchRequest::new(query))
+        .await
+    {
+        Ok(res) => prescreen_dates(dates, &res.content.results.quoted_dates(&q.currency), limit),
+        Err(e) => {
+            eprintln!("Pre-screening failed, searching every date pair: {e}");
+            dates
+        }
+    }
+}
+
+fn is_finished(status: &ResponseStatus) -> bool {
+    matches!(
+        status,
+        ResponseStatus::ResultStatusComplete | ResponseStatus::ResultStatusFailed
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::{FightsContent, ItineraryRefreshResponse, PriceChange, ResponseAction};
+    use crate::fake::{flights_response, results, FakeApi};
+    use crate::money::Money;
+
+    fn settings(max_polls: u32) -> SearchSettings {
+        SearchSettings {
+            poll_interval_milliseconds: 0,
+            deadline_seconds: 60,
+            max_polls,
+            max_concurrent_searches: 2,
+            max_trips: 200,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_works_when_polling_until_the_search_completes() {
+        let api = FakeApi::new()
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusIncomplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[]),
+            )))
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusIncomplete,
+                ResponseAction::ResultActionNotModified,
+                FightResult::default(),
+            )))
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusComplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a", "b"], &[]),
+            )));
+
+        let datasource = Datasource::new(Query::default(), api.clone(), settings(10));
+        let searched = datasource.search().await;
+
+        assert!(searched.incomplete.is_none());
+        assert_eq!(searched.results.itineraries.len(), 2);
+        assert_eq!(api.creates().len(), 1);
+        assert_eq!(api.polls(), vec!["token", "token"]);
+    }
+
+    #[tokio::test]
+    async fn it_works_when_running_out_of_polls() {
+        let mut api = FakeApi::new();
+        for _ in 0..5 {
+            api = api.push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusIncomplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[]),
+            )));
+        }
+
+        let datasource = Datasource::new(Query::default(), api.clone(), settings(2));
+        let responses = datasource.collect::<Vec<_>>().await;
+
+        assert_eq!(responses.len(), 3);
+        assert_eq!(api.polls().len(), 2);
+
+        let datasource = Datasource::new(Query::default(), api.clone(), settings(1));
+        let searched = datasource.search().await;
+        assert_eq!(searched.results.itineraries.len(), 1);
+        assert!(matches!(
+            searched
+                .incomplete
+                .unwrap()
+                .downcast_ref::<SkyscannerError>(),
+            Some(SkyscannerError::SearchIncomplete { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_works_when_a_poll_fails() {
+        let api = FakeApi::new()
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusIncomplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[]),
+            )))
+            .push_response(Err(SkyscannerError::SearchFailed {
+                session_token: "token".to_string(),
+            }));
+
+        let datasource = Datasource::new(Query::default(), api.clone(), settings(10));
+        let responses = datasource.collect::<Vec<_>>().await;
+
+        assert_eq!(responses.len(), 2);
+        assert!(responses[1].is_err());
+        assert_eq!(api.polls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_works_when_a_search_keeps_its_results_after_a_failed_poll() {
+        let api = FakeApi::new()
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusIncomplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[]),
+            )))
+            .push_response(Err(SkyscannerError::SearchFailed {
+                session_token: "token".to_string(),
+            }));
+
+        let searched = Datasource::new(Query::default(), api, settings(10))
+            .search()
+            .await;
+
+        assert_eq!(searched.results.itineraries.len(), 1);
+        assert!(matches!(
+            searched
+                .incomplete
+                .unwrap()
+                .downcast_ref::<SkyscannerError>(),
+            Some(SkyscannerError::SearchFailed { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_works_when_merging_datasources() {
+        let mut api = FakeApi::new();
+        for _ in 0..3 {
+            api = api.push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusComplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[]),
+            )));
+        }
+
+        let datasources = (0..3)
+            .map(|_| Datasource::new(Query::default(), api.clone(), settings(10)))
+            .collect::<Vec<_>>();
+        let responses = merge(datasources, 2).collect::<Vec<_>>().await;
+
+        assert_eq!(responses.len(), 3);
+        assert!(responses.iter().all(|e| e.is_ok()));
+        assert!(api.polls().is_empty());
+    }
+
+    fn refresh_response(status: ResponseStatus, results: FightResult) -> ItineraryRefreshResponse {
+        ItineraryRefreshResponse {
+            refresh_session_token: "refresh".to_string(),
+            status,
+            action: ResponseAction::ResultActionReplaced,
+            content: FightsContent { results },
+        }
+    }
+
+    #[tokio::test]
+    async fn it_works_when_refreshing_an_itinerary() {
+        let api = FakeApi::new()
+            .push_response(Ok(flights_response(
+                "token",
+                ResponseStatus::ResultStatusComplete,
+                ResponseAction::ResultActionReplaced,
+                results(&["a"], &[("x", "2500000", ""), ("y", "2600000", "")]),
+            )))
+            .push_refresh(Ok(refresh_response(
+                ResponseStatus::ResultStatusIncomplete,
+                results(&["a"], &[("x", "2500000", "PRICE_UPDATE_STATUS_PENDING")]),
+            )))
+            .push_refresh(Ok(refresh_response(
+                ResponseStatus::ResultStatusComplete,
+                results(&["a"], &[("x", "2700000", ""), ("y", "2600000", "")]),
+            )));
+
+        let query = Query::default().set_currency("TWD".to_string());
+        let mut datasource = Datasource::new(query, api.clone(), settings(10));
+        assert!(datasource.refresh("a").await.is_err());
+        while let Some(res) = datasource.next().await {
+            res.unwrap();
+        }
+
+        let refreshed = datasource.refresh("a").await.unwrap();
+        assert!(refreshed.price_changed());
+        assert!(!refreshed.pending);
+        assert_eq!(refreshed.pricing_options.len(), 2);
+        assert_eq!(
+            refreshed.changes,
+            vec![PriceChange {
+                price_option_id: "x".to_string(),
+                previous: Some(Money::from_milli(2_500_000, "TWD")),
+                current: Some(Money::from_milli(2_700_000, "TWD")),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_works_when_the_refresh_deadline_is_shorter_than_the_poll_interval() {
+        let mut api = FakeApi::new().push_response(Ok(flights_response(
+            "token",
+            ResponseStatus::ResultStatusComplete,
+            ResponseAction::ResultActionReplaced,
+            results(&["a"], &[("x", "2500000", "")]),
+        )));
+        for _ in 0..3 {
+            api = api.push_refresh(Ok(refresh_response(
+                ResponseStatus::ResultStatusIncomplete,
+                results(&["a"], &[("x", "2500000", "PRICE_UPDATE_STATUS_PENDING")]),
+            )));
+        }
+        let settings = SearchSettings {
+            poll_interval_milliseconds: 1500,
+            deadline_seconds: 1,
+            ..settings(10)
+        };
+
+        let mut datasource = Datasource::new(Query::default(), api, settings);
+        while let Some(res) = datasource.next().await {
+            res.unwrap();
+        }
+
+        let refreshed = datasource.refresh("a").await.unwrap();
+        assert!(refreshed.pending);
+        assert!(!refreshed.price_changed());
+    }
+
+    #[tokio::test]
+    async fn it_works_when_prescreening_dates_with_indicative_prices() {
+        let date = |day| NaiveDate::from_ymd_opt(2024, 6, day).unwrap();
+        let dates = vec![
+            (date(1), Some(date(4))),
+            (date(2), Some(date(5))),
+            (date(3), Some(date(6))),
+        ];
+        let (from, to) = (
+            Place::new(Some("TPE".to_string()), None),
+            Place::new(Some("HKG".to_string()), None),
+        );
+        let at = |day: u32| serde_json::json!({ "year": 2024, "month": 6, "day": day, "hour": 0, "minute": 0, "second": 0 });
+        let quote = |amount: &str, outbound: u32, inbound: u32| {
+            serde_json::json!({
+                "minPrice": { "amount": amount, "unit": "PRICE_UNIT_WHOLE" },
+                "isDirect": true,
+                "outboundLeg": {
+                    "originPlaceId": "tpe", "destinationPlaceId": "hkg",
+                    "departureDateTime": at(outbound), "marketingCarrierId": "-32222"
+                },
+                "inboundLeg": {
+                    "originPlaceId": "hkg", "destinationPlaceId": "tpe",
+                    "departureDateTime": at(inbound), "marketingCarrierId": "-32222"
+                }
+            })
+        };
+        let api = FakeApi::new().set_indicative(
+            serde_json::from_value(serde_json::json!({
+                "status": "RESULT_STATUS_COMPLETE",
+                "content": { "results": { "quotes": {
+                    "1": quote("3000", 1, 4),
+                    "2": quote("2000", 3, 6),
+                } } }
+            }))
+            .unwrap(),
+        );
+
+        let kept = prescreen(&api, &Query::default(), &from, &to, dates.clone(), 2).await;
+        assert_eq!(kept, vec![dates[2], dates[0]]);
+
+        // Without indicative prices every trip is still searched.
+        let kept = prescreen(
+            &FakeApi::new(),
+            &Query::default(),
+            &from,
+            &to,
+            dates.clone(),
+            2,
+        )
+        .await;
+        assert_eq!(kept, dates);
+    }
+}
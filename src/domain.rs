@@ -1,13 +1,18 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 
-#[derive(serde::Serialize)]
+use chrono::{Datelike, NaiveDate};
+
+use crate::error::{FormatError, SkyscannerError};
+use crate::money::{decimal, Money};
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct CreateFlightsRequest {
     pub query: Query,
 }
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Query {
     pub market: String,
     pub locale: String,
@@ -24,27 +29,34 @@ pub struct Query {
     pub near_by_airports: bool,
 }
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct QueryLeg {
     pub origin_place_id: Place,
     pub destination_place_id: Place,
     pub date: Date,
 }
 
-#[derive(serde::Serialize, Clone)]
+/// A place of a query, given either as an IATA code or as a Skyscanner entity id.
+///
+/// Indicative searches also accept anywhere as a destination.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Place {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     iata: Option<String>,
-    entry_id: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entity_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anywhere: Option<bool>,
 }
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct Date {
     year: i32,
     month: u32,
     day: u32,
 }
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub enum CabinClass {
     // Cabin class is not specified.
     #[serde(rename = "CABIN_CLASS_UNSPECIFIED")]
@@ -63,29 +75,140 @@ pub enum CabinClass {
     CabinClassFirst,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct ItineraryRefreshRequest {
+    pub itinerary_id: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct IndicativeSearchRequest {
+    pub query: IndicativeQuery,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct IndicativeQuery {
+    pub market: String,
+    pub locale: String,
+    pub currency: String,
+    pub query_legs: Vec<IndicativeQueryLeg>,
+}
+
+/// A leg of an indicative search, exactly one of the date fields is set.
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct IndicativeQueryLeg {
+    pub origin_place: IndicativeQueryPlace,
+    pub destination_place: IndicativeQueryPlace,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_range: Option<DateRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_date: Option<Date>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anytime: Option<bool>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct IndicativeQueryPlace {
+    pub query_place: Place,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct DateRange {
+    pub start_date: YearMonth,
+    pub end_date: YearMonth,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct AutosuggestRequest {
+    pub query: AutosuggestQuery,
+    pub limit: u32,
+    pub is_destination: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct AutosuggestQuery {
+    pub market: String,
+    pub locale: String,
+    pub search_term: String,
+    pub included_entity_types: Vec<PlaceType>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Markets {
     pub markets: Vec<Market>,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Market {
     pub code: String,
     pub name: String,
+    #[serde(default)]
+    pub currency: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Locales {
     pub locales: Vec<Locale>,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Locale {
     pub code: String,
     pub name: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct Currencies {
+    pub currencies: Vec<Currency>,
+}
+
+/// A currency and how its amounts are written.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Currency {
+    pub code: String,
+    pub symbol: String,
+    #[serde(default)]
+    pub thousands_separator: String,
+    #[serde(default)]
+    pub decimal_separator: String,
+    #[serde(default)]
+    pub symbol_on_left: bool,
+    #[serde(default)]
+    pub space_between_amount_and_symbol: bool,
+    #[serde(default)]
+    pub decimal_digits: u32,
+}
+
+/// The market, locale and currency closest to where a request comes from.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct NearestCulture {
+    pub market: Market,
+    pub locale: Locale,
+    pub currency: Currency,
+}
+
+/// The carriers reference data, keyed by carrier id.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct Carriers {
+    #[serde(default)]
+    pub carriers: HashMap<String, Carrier>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ApiError {
+    pub code: i32,
+    pub message: String,
+    #[serde(default)]
+    pub details: Vec<serde_json::Value>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FlightsResponse {
     pub session_token: String,
@@ -94,14 +217,44 @@ pub struct FlightsResponse {
     pub content: FightsContent,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItineraryRefreshResponse {
+    pub refresh_session_token: String,
+    pub status: ResponseStatus,
+    pub action: ResponseAction,
+    pub content: FightsContent,
+}
+
+/// The pricing options of an itinerary after a refresh, compared with the ones of the search.
+#[derive(Debug, Clone)]
+pub struct RefreshedItinerary {
+    pub itinerary_id: String,
+    pub pricing_options: Vec<PriceOption>,
+    pub changes: Vec<PriceChange>,
+    /// Some prices were still being updated when the refresh ended.
+    pub pending: bool,
+}
+
+/// A pricing option whose amount changed, `None` when it was added or disappeared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceChange {
+    pub price_option_id: String,
+    pub previous: Option<Money>,
+    pub current: Option<Money>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct FightsContent {
+    #[serde(default)]
     pub results: FightResult,
 }
 
-#[derive(serde::Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+// Polls answered with `RESULT_ACTION_NOT_MODIFIED` or `RESULT_ACTION_OMITTED` may leave out
+// any of these maps, so every one of them defaults to empty.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
 pub struct FightResult {
     pub itineraries: HashMap<String, Itinerary>,
     pub legs: HashMap<String, Leg>,
@@ -112,7 +265,7 @@ pub struct FightResult {
     pub alliances: HashMap<String, Alliance>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Itinerary {
     pub pricing_options: Vec<PriceOption>,
@@ -120,24 +273,54 @@ pub struct Itinerary {
     pub sustainability_data: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PriceOption {
     pub id: String,
     pub price: Price,
     pub agent_ids: Vec<String>,
+    /// One item per agent to book with, several for self-transfer itineraries.
+    #[serde(default)]
+    pub items: Vec<PricingItem>,
     pub transfer_type: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PricingItem {
+    pub price: Option<Price>,
+    pub agent_id: String,
+    /// The agent's booking page, valid for a limited time after the search.
+    #[serde(default)]
+    pub deep_link: String,
+    #[serde(default)]
+    pub fares: Vec<Fare>,
+    /// e.g. `PBOOK` when the booking is made on the partner's site.
+    #[serde(default)]
+    pub booking_proposition: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Fare {
+    pub segment_id: String,
+    #[serde(default)]
+    pub booking_code: String,
+    #[serde(default)]
+    pub fare_basis_code: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Price {
+    /// The amount as the API sends it, read it through `to_money`.
     pub amount: String,
     pub unit: String,
+    #[serde(default)]
     pub update_status: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Leg {
     pub origin_place_id: String,
@@ -151,7 +334,9 @@ pub struct Leg {
     pub segment_ids: Vec<String>,
 }
 
-#[derive(serde::Deserialize, Debug, Copy, Clone)]
+#[derive(
+    serde::Serialize, serde::Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord,
+)]
 pub struct ResponseDateTime {
     pub year: u16,
     pub month: u8,
@@ -161,7 +346,7 @@ pub struct ResponseDateTime {
     pub second: u8,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Segment {
     pub origin_place_id: String,
@@ -174,34 +359,41 @@ pub struct Segment {
     pub operating_carrier_id: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponsePlace {
     pub entity_id: String,
+    #[serde(default)]
     pub parent_id: String,
     pub name: String,
+    // Countries and cities of indicative searches have no code.
+    #[serde(default)]
     pub iata: String,
     #[serde(rename = "type")]
     pub kind: PlaceType,
     pub coordinates: Option<Coordinates>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Coordinates {
     pub latitude: f64,
     pub longitude: f64,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Carrier {
+    #[serde(default)]
     pub name: String,
+    #[serde(default)]
     pub alliance_id: String,
+    #[serde(default)]
     pub image_url: String,
+    #[serde(default)]
     pub iata: String,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Agent {
     pub name: String,
@@ -214,7 +406,7 @@ pub struct Agent {
     pub is_optimised_for_mobile: bool,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RatingBreakdown {
     pub customer_service: f64,
@@ -224,12 +416,82 @@ pub struct RatingBreakdown {
     pub other: f64,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Alliance {
     pub name: String,
 }
 
-#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct AutosuggestResponse {
+    #[serde(default)]
+    pub places: Vec<SuggestedPlace>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestedPlace {
+    pub entity_id: String,
+    #[serde(default)]
+    pub iata_code: String,
+    pub name: String,
+    #[serde(default)]
+    pub country_name: String,
+    #[serde(default)]
+    pub city_name: String,
+    #[serde(default)]
+    pub hierarchy: String,
+    #[serde(rename = "type")]
+    pub kind: PlaceType,
+}
+
+/// Every place flights can be searched from or to, keyed by entity id.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct GeoResponse {
+    #[serde(default)]
+    pub places: HashMap<String, ResponsePlace>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndicativeResponse {
+    pub status: ResponseStatus,
+    pub content: IndicativeContent,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IndicativeContent {
+    #[serde(default)]
+    pub results: IndicativeResults,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IndicativeResults {
+    pub quotes: HashMap<String, Quote>,
+    pub carriers: HashMap<String, Carrier>,
+    pub places: HashMap<String, ResponsePlace>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Quote {
+    pub min_price: Price,
+    pub is_direct: bool,
+    pub outbound_leg: QuoteLeg,
+    pub inbound_leg: Option<QuoteLeg>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QuoteLeg {
+    pub origin_place_id: String,
+    pub destination_place_id: String,
+    pub departure_date_time: ResponseDateTime,
+    pub marketing_carrier_id: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ResponseStatus {
     ResultStatusUnspecified,
@@ -238,7 +500,7 @@ pub enum ResponseStatus {
     ResultStatusFailed,
 }
 
-#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ResponseAction {
     ResultActionUnspecified,
@@ -247,7 +509,7 @@ pub enum ResponseAction {
     ResultActionOmitted,
 }
 
-#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum PlaceType {
     PlaceTypeUnspecified,
@@ -257,7 +519,7 @@ pub enum PlaceType {
     PlaceTypeContinent,
 }
 
-#[derive(serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum AgentType {
     AgentTypeUnspecified,
@@ -265,21 +527,66 @@ pub enum AgentType {
     AgentTypeAirline,
 }
 
+/// One itinerary ready to print, identified by its itinerary id and legs.
 #[derive(Debug)]
 pub struct FormattedResult {
-    pub price_options: Vec<f64>,
+    pub itinerary_id: String,
+    pub leg_ids: Vec<String>,
+    pub trip: TripType,
+    /// The route searched for it as typed, e.g. `TPE-NRT`, when several were searched.
+    pub route: Option<String>,
+    pub price_options: Vec<Money>,
     pub details: Vec<Details>,
+    /// How the prices are written.
+    pub currency: Currency,
+    /// Where to book the first price option.
+    pub booking_url: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Details {
+    /// The IATA code of the origin, or its place id when it's missing from the response.
+    pub origin: String,
+    pub destination: String,
     pub departure_date: ResponseDateTime,
     pub arrival_date: ResponseDateTime,
+    pub duration_in_minutes: u32,
     pub stop_count: u16,
     pub segments: Vec<String>,
     pub carrier_name: String,
 }
 
+/// The cheapest indicative quote to one destination airport of an explore search.
+#[derive(Debug)]
+pub struct Destination {
+    pub iata: String,
+    pub name: String,
+    pub price: Money,
+    pub is_direct: bool,
+    pub departure: Option<NaiveDate>,
+    /// The return day of a return quote.
+    pub back: Option<NaiveDate>,
+    /// How the price is written.
+    pub currency: Currency,
+}
+
+/// The shape of an itinerary, told apart by its legs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TripType {
+    OneWay,
+    Return,
+    MultiCity,
+}
+
+/// The orders results can be listed in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    Price,
+    Duration,
+    Departure,
+    Stops,
+}
+
 impl Default for Query {
     fn default() -> Self {
         Self {
@@ -330,17 +637,103 @@ impl Query {
         self.query_legs.push(leg);
         self
     }
+
+    pub fn set_include_carriers(mut self, ids: Vec<String>) -> Self {
+        self.include_carriers_ids = ids;
+        self
+    }
+
+    pub fn set_exclude_carriers(mut self, ids: Vec<String>) -> Self {
+        self.exclude_carriers_ids = ids;
+        self
+    }
+
+    /// Sets the carriers to include and exclude, given as IATA codes or carrier ids, as carrier
+    /// ids checked against the reference data.
+    ///
+    /// Without the reference data, e.g. offline, the codes are left for the API to check.
+    pub fn set_carriers(
+        self,
+        include: &[String],
+        exclude: &[String],
+        carriers: Option<&Carriers>,
+    ) -> Result<Self, SkyscannerError> {
+        let ids = |codes: &[String]| {
+            codes
+                .iter()
+                .map(|code| match carriers {
+                    Some(carriers) => carriers.find_id(code).unwrap_or(code).to_string(),
+                    None => code.to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let q = self
+            .set_include_carriers(ids(include))
+            .set_exclude_carriers(ids(exclude));
+        if let Some(carriers) = carriers {
+            q.validate_carriers(carriers)?;
+        }
+        Ok(q)
+    }
+
+    /// Rejects carrier ids missing from the reference data, the API silently ignores them.
+    pub fn validate_carriers(&self, carriers: &Carriers) -> Result<(), SkyscannerError> {
+        let unknown = self
+            .include_carriers_ids
+            .iter()
+            .chain(self.exclude_carriers_ids.iter())
+            .filter(|id| !carriers.carriers.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(SkyscannerError::InvalidQuery(format!(
+                "Unknown carriers {}",
+                unknown.join(", ")
+            )))
+        }
+    }
 }
 
 impl Date {
     pub fn new(year: i32, month: u32, day: u32) -> Self {
         Self { year, month, day }
     }
+
+    pub fn to_naive_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year, self.month, self.day)
+    }
 }
 
 impl Place {
-    pub fn new(iata: Option<String>, entry_id: Option<i32>) -> Self {
-        Self { iata, entry_id }
+    pub fn new(iata: Option<String>, entity_id: Option<String>) -> Self {
+        Self {
+            iata,
+            entity_id,
+            anywhere: None,
+        }
+    }
+
+    pub fn anywhere() -> Self {
+        Self {
+            iata: None,
+            entity_id: None,
+            anywhere: Some(true),
+        }
+    }
+
+    pub fn is_anywhere(&self) -> bool {
+        self.anywhere == Some(true)
+    }
+
+    pub fn iata(&self) -> Option<&str> {
+        self.iata.as_deref()
+    }
+
+    pub fn entity_id(&self) -> Option<&str> {
+        self.entity_id.as_deref()
     }
 }
 
@@ -360,6 +753,96 @@ impl CreateFlightsRequest {
     }
 }
 
+impl AutosuggestRequest {
+    /// Suggests cities and airports matching `search_term`.
+    pub fn new(market: String, locale: String, search_term: String) -> Self {
+        Self {
+            query: AutosuggestQuery {
+                market,
+                locale,
+                search_term,
+                included_entity_types: vec![PlaceType::PlaceTypeCity, PlaceType::PlaceTypeAirport],
+            },
+            limit: 10,
+            is_destination: false,
+        }
+    }
+
+    /// Suggests countries as well, e.g. for an explore destination.
+    pub fn include_countries(mut self) -> Self {
+        self.query
+            .included_entity_types
+            .push(PlaceType::PlaceTypeCountry);
+        self
+    }
+}
+
+impl IndicativeSearchRequest {
+    pub fn new(q: IndicativeQuery) -> Self {
+        Self { query: q }
+    }
+}
+
+impl IndicativeQuery {
+    pub fn new(market: String, locale: String, currency: String) -> Self {
+        Self {
+            market,
+            locale,
+            currency,
+            query_legs: vec![],
+        }
+    }
+
+    pub fn set_query_leg(mut self, leg: IndicativeQueryLeg) -> Self {
+        self.query_legs.push(leg);
+        self
+    }
+}
+
+impl IndicativeQueryLeg {
+    /// A leg departing on any day between the start and the end month, both included.
+    pub fn month_range(from: Place, to: Place, start: YearMonth, end: YearMonth) -> Self {
+        Self {
+            anytime: None,
+            date_range: Some(DateRange {
+                start_date: start,
+                end_date: end,
+            }),
+            ..Self::anytime(from, to)
+        }
+    }
+
+    pub fn fixed_date(from: Place, to: Place, date: Date) -> Self {
+        Self {
+            anytime: None,
+            fixed_date: Some(date),
+            ..Self::anytime(from, to)
+        }
+    }
+
+    pub fn anytime(from: Place, to: Place) -> Self {
+        Self {
+            origin_place: IndicativeQueryPlace { query_place: from },
+            destination_place: IndicativeQueryPlace { query_place: to },
+            date_range: None,
+            fixed_date: None,
+            anytime: Some(true),
+        }
+    }
+}
+
+impl YearMonth {
+    pub fn new(year: i32, month: u32) -> Self {
+        Self { year, month }
+    }
+}
+
+impl From<NaiveDate> for YearMonth {
+    fn from(value: NaiveDate) -> Self {
+        Self::new(value.year(), value.month())
+    }
+}
+
 impl Display for ResponseDateTime {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -370,112 +853,488 @@ impl Display for ResponseDateTime {
     }
 }
 
+impl ResponseDateTime {
+    pub fn to_naive_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year as i32, self.month as u32, self.day as u32)
+    }
+}
+
+impl PriceOption {
+    /// The deep link of the first item, the one to follow to book this option.
+    pub fn booking_url(&self) -> Option<&str> {
+        self.items
+            .iter()
+            .map(|e| e.deep_link.as_str())
+            .find(|e| !e.is_empty())
+    }
+}
+
+impl Currency {
+    /// A currency known only by its code, written like `2,500.00 TWD`.
+    pub fn plain(code: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            symbol: code.to_string(),
+            thousands_separator: ",".to_string(),
+            decimal_separator: ".".to_string(),
+            symbol_on_left: false,
+            space_between_amount_and_symbol: true,
+            decimal_digits: 2,
+        }
+    }
+}
+
+impl Price {
+    /// The amount in milli-units, `unit` tells how many of them one unit of `amount` is.
+    ///
+    /// A micro-unit amount is rounded half away from zero, an unknown unit has no value.
+    pub fn milli(&self) -> Option<i64> {
+        let amount = self.amount.parse::<i64>().ok()?;
+        match self.unit.as_str() {
+            "PRICE_UNIT_WHOLE" => amount.checked_mul(1000),
+            "PRICE_UNIT_CENTI" => amount.checked_mul(10),
+            "PRICE_UNIT_MILLI" => Some(amount),
+            "PRICE_UNIT_MICRO" => Some(amount / 1000 + amount % 1000 / 500),
+            _ => None,
+        }
+    }
+
+    /// The unit is a scale, not a currency, the currency is the one of the query.
+    pub fn to_money(&self, currency: &str) -> Option<Money> {
+        Some(Money::from_milli(self.milli()?, currency))
+    }
+
+    /// The agent is still confirming this price, it may change on the next poll.
+    pub fn is_pending(&self) -> bool {
+        self.update_status == "PRICE_UPDATE_STATUS_PENDING"
+    }
+}
+
+impl RefreshedItinerary {
+    /// Prices are read in `currency`, the one of the query.
+    pub fn new(
+        itinerary_id: &str,
+        currency: &str,
+        previous: &[PriceOption],
+        current: Vec<PriceOption>,
+    ) -> Self {
+        let amount = |options: &[PriceOption], id: &str| {
+            options
+                .iter()
+                .find(|e| e.id == id)
+                .and_then(|e| e.price.to_money(currency))
+        };
+
+        let mut ids = previous
+            .iter()
+            .chain(current.iter())
+            .map(|e| e.id.as_str())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+
+        let changes = ids
+            .into_iter()
+            .map(|id| PriceChange {
+                price_option_id: id.to_string(),
+                previous: amount(previous, id),
+                current: amount(&current, id),
+            })
+            .filter(|change| change.previous != change.current)
+            .collect();
+
+        Self {
+            itinerary_id: itinerary_id.to_string(),
+            pending: current.iter().any(|e| e.price.is_pending()),
+            pricing_options: current,
+            changes,
+        }
+    }
+
+    pub fn price_changed(&self) -> bool {
+        !self.changes.is_empty()
+    }
+}
+
 impl Display for Price {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let amount = self.amount.parse::<f64>();
-        match amount {
-            Ok(amt) => write!(f, "{}", amt / 1000.0),
-            Err(_) => write!(f, "no price"),
+        match self.milli() {
+            Some(milli) => write!(f, "{}", decimal(milli)),
+            None => write!(f, "no price"),
+        }
+    }
+}
+
+impl Carriers {
+    /// Finds the id of a carrier given either as its id or as its IATA code.
+    pub fn find_id(&self, code: &str) -> Option<&str> {
+        if let Some((id, _)) = self.carriers.get_key_value(code) {
+            return Some(id);
         }
+        self.carriers
+            .iter()
+            .find(|(_, carrier)| carrier.iata.eq_ignore_ascii_case(code))
+            .map(|(id, _)| id.as_str())
     }
 }
 
 impl FightResult {
-    pub fn format(self) -> Vec<FormattedResult> {
-        self.itineraries
+    /// Completes the carriers of this result from the reference data, adding the ones the
+    /// response left out and filling in missing names and IATA codes.
+    pub fn fill_carriers(&mut self, reference: &Carriers) {
+        let ids = self
+            .legs
             .values()
-            .map(|itinerary| {
-                let stops = itinerary
+            .flat_map(|leg| {
+                leg.marketing_carrier_ids
+                    .iter()
+                    .chain(leg.operating_carrier_ids.iter())
+            })
+            .chain(self.segments.values().flat_map(|segment| {
+                segment
+                    .marking_carrier_id
+                    .iter()
+                    .chain(segment.operating_carrier_id.iter())
+            }))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for id in ids {
+            let Some(known) = reference.carriers.get(&id) else {
+                continue;
+            };
+            let carrier = self.carriers.entry(id).or_insert_with(|| known.clone());
+            if carrier.name.is_empty() {
+                carrier.name = known.name.clone();
+            }
+            if carrier.iata.is_empty() {
+                carrier.iata = known.iata.clone();
+            }
+        }
+    }
+
+    /// Applies the content of a create or poll response to this snapshot.
+    ///
+    /// `RESULT_ACTION_REPLACED` carries the full result set and replaces the snapshot,
+    /// every other action leaves the snapshot untouched.
+    pub fn apply(&mut self, action: &ResponseAction, results: FightResult) {
+        if *action == ResponseAction::ResultActionReplaced {
+            *self = results;
+        }
+    }
+
+    /// Formats every itinerary, prices are read in `currency`, the one of the query.
+    ///
+    /// An itinerary referring to a leg missing from the response can't be shown and comes
+    /// back as an error, a missing carrier only shows up as its id.
+    pub fn format(self, currency: &Currency) -> Vec<Result<FormattedResult, FormatError>> {
+        self.itineraries
+            .iter()
+            .map(|(itinerary_id, itinerary)| {
+                let legs = itinerary
                     .leg_ids
                     .iter()
                     .map(|leg_id| {
-                        let leg = self.legs.get(leg_id);
-                        // friendly assert
-                        assert!(leg.is_some());
-                        let leg = leg.unwrap();
-                        let carrier_name =
-                            if let Some(carrier_id) = leg.marketing_carrier_ids.first() {
-                                let carrier = self.carriers.get(carrier_id);
-                                // friendly assert
-                                assert!(carrier.is_some());
-                                carrier.unwrap().name.to_string()
-                            } else if let Some(carrier_id) = leg.operating_carrier_ids.first() {
-                                let carrier = self.carriers.get(carrier_id);
-                                // friendly assert
-                                assert!(carrier.is_some());
-                                carrier.unwrap().name.to_string()
-                            } else {
-                                "Unknown carrier name".to_string()
-                            };
-
-                        let segments = leg
-                            .segment_ids
-                            .iter()
-                            .map(|segment_id| match self.segments.get(segment_id) {
-                                None => segment_id.clone(),
-                                Some(segment) => {
-                                    let destination_place = self
-                                        .places
-                                        .get(&segment.destination_place_id)
-                                        .map(|place| place.iata.clone())
-                                        .unwrap_or_else(|| segment.destination_place_id.clone());
-
-                                    format!("...{}", destination_place)
-                                }
+                        self.legs
+                            .get(leg_id)
+                            .ok_or_else(|| FormatError::MissingLeg {
+                                itinerary_id: itinerary_id.clone(),
+                                leg_id: leg_id.clone(),
                             })
-                            .collect::<Vec<_>>();
-                        Details {
-                            departure_date: leg.departure_date_time,
-                            arrival_date: leg.arrival_date_time,
-                            stop_count: leg.stop_count,
-                            segments,
-                            carrier_name,
-                        }
                     })
-                    .collect::<Vec<_>>();
-                let prices = itinerary
+                    .collect::<Result<Vec<_>, FormatError>>()?;
+                let stops = legs.iter().map(|leg| self.details(leg)).collect();
+                let priced = itinerary
                     .pricing_options
                     .iter()
-                    .filter(|p| !p.price.amount.is_empty())
-                    .filter_map(|p| p.price.amount.parse::<f64>().ok())
+                    .filter_map(|p| Some((p.price.to_money(&currency.code)?, p)))
                     .collect::<Vec<_>>();
+                let booking_url = priced
+                    .first()
+                    .and_then(|(_, p)| p.booking_url())
+                    .map(|e| e.to_string());
 
-                FormattedResult {
-                    price_options: prices,
+                Ok(FormattedResult {
+                    itinerary_id: itinerary_id.clone(),
+                    leg_ids: itinerary.leg_ids.clone(),
+                    trip: TripType::of(&legs),
+                    route: None,
+                    price_options: priced.into_iter().map(|(price, _)| price).collect(),
                     details: stops,
-                }
+                    currency: currency.clone(),
+                    booking_url,
+                })
             })
             .collect::<Vec<_>>()
     }
+
+    fn details(&self, leg: &Leg) -> Details {
+        let carrier_name = leg
+            .marketing_carrier_ids
+            .first()
+            .or(leg.operating_carrier_ids.first())
+            .map(|carrier_id| {
+                self.carriers
+                    .get(carrier_id)
+                    .filter(|carrier| !carrier.name.is_empty())
+                    .map(|carrier| carrier.name.clone())
+                    .unwrap_or_else(|| carrier_id.clone())
+            })
+            .unwrap_or_else(|| "Unknown carrier name".to_string());
+        let iata = |place_id: &String| {
+            self.places
+                .get(place_id)
+                .map(|place| place.iata.clone())
+                .filter(|iata| !iata.is_empty())
+                .unwrap_or_else(|| place_id.clone())
+        };
+
+        let segments = leg
+            .segment_ids
+            .iter()
+            .map(|segment_id| match self.segments.get(segment_id) {
+                None => segment_id.clone(),
+                Some(segment) => {
+                    let destination_place = self
+                        .places
+                        .get(&segment.destination_place_id)
+                        .map(|place| place.iata.clone())
+                        .unwrap_or_else(|| segment.destination_place_id.clone());
+
+                    format!("...{}", destination_place)
+                }
+            })
+            .collect::<Vec<_>>();
+        Details {
+            origin: iata(&leg.origin_place_id),
+            destination: iata(&leg.destination_place_id),
+            departure_date: leg.departure_date_time,
+            arrival_date: leg.arrival_date_time,
+            duration_in_minutes: leg.duration_in_minutes,
+            stop_count: leg.stop_count,
+            segments,
+            carrier_name,
+        }
+    }
+}
+
+impl IndicativeResults {
+    /// Every quote as its outbound date, inbound date (for return quotes) and price in
+    /// `currency`, the one of the query.
+    pub fn quoted_dates(&self, currency: &str) -> Vec<(NaiveDate, Option<NaiveDate>, Money)> {
+        self.quotes
+            .values()
+            .filter_map(|quote| {
+                let outbound = quote.outbound_leg.departure_date_time.to_naive_date()?;
+                let inbound = quote
+                    .inbound_leg
+                    .as_ref()
+                    .and_then(|leg| leg.departure_date_time.to_naive_date());
+                let price = quote.min_price.to_money(currency)?;
+                Some((outbound, inbound, price))
+            })
+            .collect()
+    }
+
+    /// The cheapest quote of every destination airport, cheapest destination first. Prices
+    /// are read in `currency`, the one of the query.
+    pub fn cheapest_destinations(&self, currency: &Currency) -> Vec<Destination> {
+        let mut cheapest: HashMap<&str, (&Quote, Money)> = HashMap::new();
+        for quote in self.quotes.values() {
+            let Some(price) = quote.min_price.to_money(&currency.code) else {
+                continue;
+            };
+            let destination = quote.outbound_leg.destination_place_id.as_str();
+            match cheapest.get(destination) {
+                Some((_, kept)) if kept.milli() <= price.milli() => {}
+                _ => {
+                    cheapest.insert(destination, (quote, price));
+                }
+            }
+        }
+
+        let mut destinations = cheapest
+            .into_iter()
+            .map(|(place_id, (quote, price))| {
+                let place = self.places.get(place_id);
+                Destination {
+                    iata: place
+                        .map(|e| e.iata.clone())
+                        .filter(|e| !e.is_empty())
+                        .unwrap_or_else(|| place_id.to_string()),
+                    name: place.map(|e| e.name.clone()).unwrap_or_default(),
+                    price,
+                    is_direct: quote.is_direct,
+                    departure: quote.outbound_leg.departure_date_time.to_naive_date(),
+                    back: quote
+                        .inbound_leg
+                        .as_ref()
+                        .and_then(|e| e.departure_date_time.to_naive_date()),
+                    currency: currency.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+        destinations.sort_by(|a, b| {
+            a.price
+                .milli()
+                .cmp(&b.price.milli())
+                .then_with(|| a.iata.cmp(&b.iata))
+        });
+        destinations
+    }
+
+    /// Adds the quotes and places of another search, e.g. from another origin.
+    pub fn merge(&mut self, other: IndicativeResults) {
+        self.quotes.extend(other.quotes);
+        self.carriers.extend(other.carriers);
+        self.places.extend(other.places);
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() || self.name == self.iata {
+            write!(f, "{}:", self.iata)?;
+        } else {
+            write!(f, "{} ({}):", self.name, self.iata)?;
+        }
+        write!(f, "\t{}", self.price.format(&self.currency))?;
+        match (self.departure, self.back) {
+            (Some(departure), Some(back)) => write!(f, "\t{departure} -> {back}")?,
+            (Some(departure), None) => write!(f, "\t{departure}")?,
+            _ => {}
+        }
+        if self.is_direct {
+            write!(f, "\t(direct)")?;
+        }
+        Ok(())
+    }
+}
+
+impl FormattedResult {
+    /// The first price.
+    pub fn price(&self) -> Option<&Money> {
+        self.price_options.first()
+    }
+
+    pub fn duration_in_minutes(&self) -> u32 {
+        self.details.iter().map(|e| e.duration_in_minutes).sum()
+    }
+
+    pub fn departure(&self) -> Option<ResponseDateTime> {
+        self.details.first().map(|e| e.departure_date)
+    }
+
+    pub fn stop_count(&self) -> u32 {
+        self.details.iter().map(|e| u32::from(e.stop_count)).sum()
+    }
+
+    /// The cheapest priced result of every route, by route.
+    pub fn cheapest_by_route(results: &[Self]) -> BTreeMap<&str, &Self> {
+        let mut cheapest: BTreeMap<&str, &Self> = BTreeMap::new();
+        for result in results.iter().filter(|e| e.price().is_some()) {
+            let route = result.route.as_deref().unwrap_or_default();
+            match cheapest.get(route) {
+                Some(kept) if SortBy::Price.compare(result, kept) != Ordering::Less => {}
+                _ => {
+                    cheapest.insert(route, result);
+                }
+            }
+        }
+        cheapest
+    }
+
+    /// Keeps the cheapest of the results sharing an identity, e.g. the same itinerary found
+    /// by two searches, in no particular order.
+    pub fn dedup(results: Vec<Self>) -> Vec<Self> {
+        let mut unique: HashSet<Self> = HashSet::with_capacity(results.len());
+        for result in results {
+            let cheaper = unique.get(&result).map_or(true, |kept| {
+                SortBy::Price.compare(&result, kept) == Ordering::Less
+            });
+            if cheaper {
+                unique.replace(result);
+            }
+        }
+        unique.into_iter().collect()
+    }
 }
 
+/// Two results are the same itinerary when their ids and legs match, whatever the prices.
 impl PartialEq<Self> for FormattedResult {
     fn eq(&self, other: &Self) -> bool {
-        self.price_options.first().unwrap() - other.price_options.first().unwrap() <= f64::EPSILON
+        self.itinerary_id == other.itinerary_id && self.leg_ids == other.leg_ids
     }
 }
 
-impl PartialOrd<Self> for FormattedResult {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.price_options
-            .first()
-            .unwrap()
-            .partial_cmp(other.price_options.first().unwrap())
+impl Eq for FormattedResult {}
+
+impl std::hash::Hash for FormattedResult {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.itinerary_id.hash(state);
+        self.leg_ids.hash(state);
     }
 }
 
-impl Eq for FormattedResult {}
+impl SortBy {
+    /// Orders by the key, then by price and identity so the order is total. Missing values,
+    /// e.g. an itinerary without any price, come last.
+    pub fn compare(&self, a: &FormattedResult, b: &FormattedResult) -> Ordering {
+        let by_key = match self {
+            SortBy::Price => Ordering::Equal,
+            SortBy::Duration => a.duration_in_minutes().cmp(&b.duration_in_minutes()),
+            SortBy::Departure => {
+                let departure = |e: &FormattedResult| {
+                    let departure = e.departure();
+                    (departure.is_none(), departure)
+                };
+                departure(a).cmp(&departure(b))
+            }
+            SortBy::Stops => a.stop_count().cmp(&b.stop_count()),
+        };
+        by_key
+            .then_with(|| match (a.price(), b.price()) {
+                (Some(a), Some(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            })
+            .then_with(|| a.itinerary_id.cmp(&b.itinerary_id))
+            .then_with(|| a.leg_ids.cmp(&b.leg_ids))
+    }
+}
 
-impl Ord for FormattedResult {
-    fn cmp(&self, other: &Self) -> Ordering {
-        let b = other.price_options.first().unwrap();
-        let a = self.price_options.first().unwrap();
-        a.total_cmp(b)
+impl TripType {
+    /// A return trip flies back the way it went, anything else with several legs is
+    /// multi-city, e.g. an open-jaw.
+    pub fn of(legs: &[&Leg]) -> Self {
+        match legs {
+            [_] => TripType::OneWay,
+            [outbound, inbound]
+                if outbound.origin_place_id == inbound.destination_place_id
+                    && outbound.destination_place_id == inbound.origin_place_id =>
+            {
+                TripType::Return
+            }
+            _ => TripType::MultiCity,
+        }
+    }
+}
+
+impl Display for TripType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TripType::OneWay => write!(f, "One-way"),
+            TripType::Return => write!(f, "Return"),
+            TripType::MultiCity => write!(f, "Multi-city"),
+        }
     }
 }
 
 impl Display for FormattedResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.route {
+            Some(route) => writeln!(f, "{} {route}:", self.trip)?,
+            None => writeln!(f, "{}:", self.trip)?,
+        }
         for stop in self.details.iter() {
             if stop.stop_count > 0 {
                 let formatted_stops = stop
@@ -488,7 +1347,9 @@ impl Display for FormattedResult {
 
                 writeln!(
                     f,
-                    "Carrier({}):\t{} -> {}\t(stop count: {})\t({})",
+                    "{}-{}\tCarrier({}):\t{} -> {}\t(stop count: {})\t({})",
+                    stop.origin,
+                    stop.destination,
                     stop.carrier_name,
                     stop.departure_date,
                     stop.arrival_date,
@@ -498,17 +1359,444 @@ impl Display for FormattedResult {
             } else {
                 writeln!(
                     f,
-                    "Carrier({}):\t{} -> {}",
-                    stop.carrier_name, stop.departure_date, stop.arrival_date,
+                    "{}-{}\tCarrier({}):\t{} -> {}",
+                    stop.origin,
+                    stop.destination,
+                    stop.carrier_name,
+                    stop.departure_date,
+                    stop.arrival_date,
                 )
-            }
-            .expect("Can't flush data");
+            }?;
         }
 
-        if let Some(price) = self.price_options.first() {
-            writeln!(f, "Price: {}", price / 1000.0).expect("Can't flush data");
+        match self.price_options.first() {
+            Some(price) => writeln!(f, "Price: {}", price.format(&self.currency))?,
+            None => writeln!(f, "Price: unavailable")?,
+        }
+        if let Some(url) = &self.booking_url {
+            writeln!(f, "Book: {url}")?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::SkyscannerApi;
+    use crate::cache::ReferenceCache;
+    use crate::fake::{formatted, results, FakeApi};
+
+    #[test]
+    fn it_works_when_applying_response_actions() {
+        let mut snapshot = FightResult::default();
+
+        snapshot.apply(
+            &ResponseAction::ResultActionReplaced,
+            results(&["a", "b"], &[]),
+        );
+        assert_eq!(snapshot.itineraries.len(), 2);
+
+        snapshot.apply(
+            &ResponseAction::ResultActionNotModified,
+            FightResult::default(),
+        );
+        assert_eq!(snapshot.itineraries.len(), 2);
+
+        snapshot.apply(&ResponseAction::ResultActionOmitted, results(&["c"], &[]));
+        assert_eq!(snapshot.itineraries.len(), 2);
+
+        snapshot.apply(
+            &ResponseAction::ResultActionReplaced,
+            results(&["b", "c", "d"], &[]),
+        );
+        let mut ids = snapshot.itineraries.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec!["b", "c", "d"]);
+    }
+
+    fn carriers() -> Carriers {
+        serde_json::from_value(serde_json::json!({
+            "carriers": {
+                "-32222": { "name": "Cathay Pacific", "iata": "CX" },
+                "-31736": { "name": "EVA Air", "iata": "BR" },
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_works_when_filling_carriers_from_reference_data() {
+        let mut results = serde_json::from_value::<FightResult>(serde_json::json!({
+            "legs": {
+                "leg": {
+                    "originPlaceId": "1", "destinationPlaceId": "2",
+                    "departureDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 8, "minute": 0, "second": 0 },
+                    "arrivalDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 10, "minute": 0, "second": 0 },
+                    "durationInMinutes": 120, "stopCount": 0,
+                    "marketingCarrierIds": ["-32222"], "operatingCarrierIds": ["-31736"],
+                    "segmentIds": []
+                }
+            },
+            "carriers": { "-31736": { "name": "EVA Air" } }
+        }))
+        .unwrap();
+
+        results.fill_carriers(&carriers());
+        assert_eq!(results.carriers["-32222"].name, "Cathay Pacific");
+        assert_eq!(results.carriers["-31736"].iata, "BR");
+    }
+
+    #[test]
+    fn it_works_when_validating_carriers() {
+        let carriers = carriers();
+        assert_eq!(carriers.find_id("cx"), Some("-32222"));
+        assert_eq!(carriers.find_id("-31736"), Some("-31736"));
+        assert_eq!(carriers.find_id("JX"), None);
+
+        let q = Query::default().set_include_carriers(vec!["-32222".to_string()]);
+        assert!(q.validate_carriers(&carriers).is_ok());
+
+        let q = q.set_exclude_carriers(vec!["JX".to_string()]);
+        assert!(matches!(
+            q.validate_carriers(&carriers),
+            Err(SkyscannerError::InvalidQuery(message)) if message.contains("JX")
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_works_when_setting_carriers_from_the_loaded_reference_data() {
+        let api = FakeApi::new().set_carriers(carriers());
+        let cache = ReferenceCache::disabled();
+        let loaded = cache
+            .get_or_fetch("carriers", || api.get_carriers())
+            .await
+            .unwrap();
+
+        let codes = |codes: &[&str]| codes.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        let q = Query::default()
+            .set_carriers(&codes(&["cx"]), &codes(&["-31736"]), Some(&loaded))
+            .unwrap();
+        assert_eq!(q.include_carriers_ids, vec!["-32222"]);
+        assert_eq!(q.exclude_carriers_ids, vec!["-31736"]);
+
+        assert!(Query::default()
+            .set_carriers(&codes(&["JX"]), &[], Some(&loaded))
+            .is_err());
+        let q = Query::default()
+            .set_carriers(&codes(&["JX"]), &[], None)
+            .unwrap();
+        assert_eq!(q.include_carriers_ids, vec!["JX"]);
+    }
+
+    #[test]
+    fn it_works_when_formatting_the_booking_url() {
+        let results = serde_json::from_value::<FightResult>(serde_json::json!({
+            "itineraries": {
+                "a": {
+                    "legIds": ["leg"],
+                    "pricingOptions": [{
+                        "id": "x",
+                        "price": { "amount": "2500000", "unit": "PRICE_UNIT_MILLI" },
+                        "agentIds": ["agent"],
+                        "transferType": "TRANSFER_TYPE_MANAGED",
+                        "items": [{
+                            "price": { "amount": "2500000", "unit": "PRICE_UNIT_MILLI" },
+                            "agentId": "agent",
+                            "deepLink": "https://example.com/book/x",
+                            "fares": [{ "segmentId": "s", "bookingCode": "Y", "fareBasisCode": "YOW" }],
+                            "bookingProposition": "PBOOK"
+                        }]
+                    }]
+                }
+            },
+            "legs": {
+                "leg": {
+                    "originPlaceId": "1", "destinationPlaceId": "2",
+                    "departureDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 8, "minute": 0, "second": 0 },
+                    "arrivalDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 10, "minute": 0, "second": 0 },
+                    "durationInMinutes": 120, "stopCount": 0,
+                    "marketingCarrierIds": [], "operatingCarrierIds": [],
+                    "segmentIds": []
+                }
+            }
+        }))
+        .unwrap();
+
+        let option = &results.itineraries["a"].pricing_options[0];
+        assert_eq!(option.items[0].fares[0].fare_basis_code, "YOW");
+
+        let formatted = results.format(&Currency::plain("TWD"));
+        let formatted = formatted[0].as_ref().unwrap();
+        assert_eq!(
+            formatted.booking_url.as_deref(),
+            Some("https://example.com/book/x")
+        );
+        assert_eq!(formatted.trip, TripType::OneWay);
+        assert!(formatted.to_string().starts_with("One-way:\n"));
+        assert!(formatted
+            .to_string()
+            .ends_with("Book: https://example.com/book/x\n"));
+    }
+
+    #[test]
+    fn it_works_when_an_itinerary_is_incomplete() {
+        let results = serde_json::from_value::<FightResult>(serde_json::json!({
+            "itineraries": {
+                "missing": { "legIds": ["gone"], "pricingOptions": [] },
+                "unpriced": { "legIds": ["leg"], "pricingOptions": [] },
+                "priced": {
+                    "legIds": ["leg"],
+                    "pricingOptions": [{
+                        "id": "x",
+                        "price": { "amount": "2500000", "unit": "PRICE_UNIT_MILLI" },
+                        "agentIds": ["agent"],
+                        "transferType": "TRANSFER_TYPE_MANAGED"
+                    }]
+                }
+            },
+            "legs": {
+                "leg": {
+                    "originPlaceId": "1", "destinationPlaceId": "2",
+                    "departureDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 8, "minute": 0, "second": 0 },
+                    "arrivalDateTime": { "year": 2023, "month": 6, "day": 2, "hour": 10, "minute": 0, "second": 0 },
+                    "durationInMinutes": 120, "stopCount": 0,
+                    "marketingCarrierIds": ["-99"], "operatingCarrierIds": [],
+                    "segmentIds": []
+                }
+            }
+        }))
+        .unwrap();
+
+        let (formatted, errors): (Vec<_>, Vec<_>) = results
+            .format(&Currency::plain("TWD"))
+            .into_iter()
+            .partition(|e| e.is_ok());
+        assert_eq!(
+            errors
+                .into_iter()
+                .map(|e| e.unwrap_err())
+                .collect::<Vec<_>>(),
+            vec![FormatError::MissingLeg {
+                itinerary_id: "missing".to_string(),
+                leg_id: "gone".to_string(),
+            }]
+        );
+
+        let mut formatted = formatted
+            .into_iter()
+            .map(|e| e.unwrap())
+            .collect::<Vec<_>>();
+        formatted.sort_by(|a, b| SortBy::Price.compare(a, b));
+        assert_eq!(formatted[0].price_options[0].milli(), 2_500_000);
+        assert!(formatted[1].price_options.is_empty());
+        assert_eq!(formatted[1].details[0].carrier_name, "-99");
+        assert!(formatted[1].to_string().ends_with("Price: unavailable\n"));
+    }
+
+    #[test]
+    fn it_works_when_sorting_and_deduplicating_results() {
+        let a = formatted("a", Some(3_000_000), 9, 60, 0);
+        let b = formatted("b", Some(2_000_000), 7, 180, 2);
+        let c = formatted("c", None, 8, 120, 1);
+
+        // Equality is the identity, it's symmetric whatever the prices.
+        let cheaper_a = formatted("a", Some(1_000_000), 9, 60, 0);
+        assert_eq!(a, cheaper_a);
+        assert_eq!(cheaper_a, a);
+        assert_ne!(a, b);
+        assert_ne!(b, a);
+
+        let ids = |order: SortBy| {
+            let mut results = vec![
+                formatted("a", Some(3_000_000), 9, 60, 0),
+                formatted("b", Some(2_000_000), 7, 180, 2),
+                formatted("c", None, 8, 120, 1),
+            ];
+            results.sort_by(|a, b| order.compare(a, b));
+            results
+                .into_iter()
+                .map(|e| e.itinerary_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(SortBy::Price), vec!["b", "a", "c"]);
+        assert_eq!(ids(SortBy::Duration), vec!["a", "c", "b"]);
+        assert_eq!(ids(SortBy::Departure), vec!["b", "c", "a"]);
+        assert_eq!(ids(SortBy::Stops), vec!["a", "c", "b"]);
+        assert_eq!(SortBy::Price.compare(&a, &a), Ordering::Equal);
+
+        let unique = FormattedResult::dedup(vec![a, b, c, cheaper_a]);
+        assert_eq!(unique.len(), 3);
+        let kept = unique.iter().find(|e| e.itinerary_id == "a").unwrap();
+        assert_eq!(kept.price(), Some(&Money::from_milli(1_000_000, "TWD")));
+        assert_eq!(
+            <SortBy as clap::ValueEnum>::from_str("stops", false).unwrap(),
+            SortBy::Stops
+        );
+        assert!(<SortBy as clap::ValueEnum>::from_str("fastest", false).is_err());
+    }
+
+    #[test]
+    fn it_works_when_formatting_a_multi_city_itinerary() {
+        let leg = |origin: &str, destination: &str, day: u8| {
+            serde_json::json!({
+                "originPlaceId": origin, "destinationPlaceId": destination,
+                "departureDateTime": { "year": 2024, "month": 6, "day": day, "hour": 8, "minute": 0, "second": 0 },
+                "arrivalDateTime": { "year": 2024, "month": 6, "day": day, "hour": 12, "minute": 0, "second": 0 },
+                "durationInMinutes": 180, "stopCount": 0,
+                "marketingCarrierIds": [], "operatingCarrierIds": [],
+                "segmentIds": []
+            })
+        };
+        let place = |iata: &str| serde_json::json!({ "entityId": iata, "name": iata, "iata": iata, "type": "PLACE_TYPE_AIRPORT" });
+        let results = serde_json::from_value::<FightResult>(serde_json::json!({
+            "itineraries": {
+                "open-jaw": { "legIds": ["out", "in"], "pricingOptions": [] },
+                "return": { "legIds": ["out", "back"], "pricingOptions": [] }
+            },
+            "legs": {
+                "out": leg("1", "2", 1),
+                "in": leg("3", "1", 8),
+                "back": leg("2", "1", 8)
+            },
+            "places": { "1": place("TPE"), "2": place("NRT"), "3": place("KIX") }
+        }))
+        .unwrap();
+
+        let formatted = results
+            .format(&Currency::plain("TWD"))
+            .into_iter()
+            .map(|e| e.unwrap())
+            .map(|e| (e.itinerary_id.clone(), e))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(formatted["return"].trip, TripType::Return);
+
+        let open_jaw = &formatted["open-jaw"];
+        assert_eq!(open_jaw.trip, TripType::MultiCity);
+        let lines = open_jaw.to_string();
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Multi-city:");
+        assert!(lines[1].starts_with("TPE-NRT\tCarrier"));
+        assert!(lines[2].starts_with("KIX-TPE\tCarrier"));
+    }
+
+    #[test]
+    fn it_works_when_finding_the_cheapest_result_of_every_route() {
+        let routed = |id: &str, price: Option<i64>, route: &str| FormattedResult {
+            route: Some(route.to_string()),
+            ..formatted(id, price, 8, 60, 0)
+        };
+        let results = vec![
+            routed("a", Some(3_000_000), "TPE-NRT"),
+            routed("b", Some(2_000_000), "TPE-NRT"),
+            routed("c", Some(2_500_000), "TSA-HND"),
+            routed("d", None, "KHH-KIX"),
+        ];
+
+        let cheapest = FormattedResult::cheapest_by_route(&results);
+        assert_eq!(
+            cheapest
+                .iter()
+                .map(|(route, e)| (*route, e.itinerary_id.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("TPE-NRT", "b"), ("TSA-HND", "c")]
+        );
+        assert!(results[0].to_string().starts_with("One-way TPE-NRT:\n"));
+    }
+
+    #[test]
+    fn it_works_when_ranking_explored_destinations() {
+        let quote = |destination: &str, amount: &str, day: u8| {
+            serde_json::json!({
+                "minPrice": { "amount": amount, "unit": "PRICE_UNIT_WHOLE" },
+                "isDirect": day % 2 == 0,
+                "outboundLeg": {
+                    "originPlaceId": "tpe", "destinationPlaceId": destination,
+                    "departureDateTime": { "year": 2024, "month": 6, "day": day, "hour": 0, "minute": 0, "second": 0 },
+                    "marketingCarrierId": "-32222"
+                }
+            })
+        };
+        let mut results = serde_json::from_value::<IndicativeResults>(serde_json::json!({
+            "quotes": {
+                "1": quote("nrt", "3000", 1),
+                "2": quote("nrt", "2000", 2),
+                "3": quote("kix", "2500", 3),
+            },
+            "places": {
+                "nrt": { "entityId": "nrt", "name": "Tokyo Narita", "iata": "NRT", "type": "PLACE_TYPE_AIRPORT" },
+                "kix": { "entityId": "kix", "name": "Osaka Kansai", "iata": "KIX", "type": "PLACE_TYPE_AIRPORT" },
+            }
+        }))
+        .unwrap();
+        results.merge(
+            serde_json::from_value(serde_json::json!({
+                "quotes": { "4": quote("bkk", "1500", 5) }
+            }))
+            .unwrap(),
+        );
+
+        let destinations = results.cheapest_destinations(&Currency::plain("TWD"));
+        assert_eq!(
+            destinations
+                .iter()
+                .map(|e| (e.iata.as_str(), e.price.milli()))
+                .collect::<Vec<_>>(),
+            vec![("bkk", 1_500_000), ("NRT", 2_000_000), ("KIX", 2_500_000)]
+        );
+        assert_eq!(
+            destinations[1].to_string(),
+            "Tokyo Narita (NRT):\t2,000.00 TWD\t2024-06-02\t(direct)"
+        );
+        assert_eq!(
+            serde_json::to_value(Place::anywhere()).unwrap(),
+            serde_json::json!({ "anywhere": true })
+        );
+    }
+
+    #[test]
+    fn it_works_when_reading_price_units() {
+        let price = |amount: &str, unit: &str| Price {
+            amount: amount.to_string(),
+            unit: unit.to_string(),
+            update_status: "".to_string(),
+        };
+        assert_eq!(price("2500", "PRICE_UNIT_WHOLE").milli(), Some(2_500_000));
+        assert_eq!(price("250000", "PRICE_UNIT_CENTI").milli(), Some(2_500_000));
+        assert_eq!(
+            price("2500000", "PRICE_UNIT_MILLI").milli(),
+            Some(2_500_000)
+        );
+        assert_eq!(
+            price("2500000000", "PRICE_UNIT_MICRO").milli(),
+            Some(2_500_000)
+        );
+        assert_eq!(
+            price("2500000499", "PRICE_UNIT_MICRO").milli(),
+            Some(2_500_000)
+        );
+        assert_eq!(
+            price("2500000500", "PRICE_UNIT_MICRO").milli(),
+            Some(2_500_001)
+        );
+        assert_eq!(price("-1500", "PRICE_UNIT_MICRO").milli(), Some(-2));
+        assert_eq!(price("2500", "PRICE_UNIT_UNSPECIFIED").milli(), None);
+
+        // The same amount in two units is not a change.
+        let option = |price: Price| PriceOption {
+            id: "x".to_string(),
+            price,
+            agent_ids: vec![],
+            items: vec![],
+            transfer_type: "".to_string(),
+        };
+        let refreshed = RefreshedItinerary::new(
+            "a",
+            "TWD",
+            &[option(price("2500", "PRICE_UNIT_WHOLE"))],
+            vec![option(price("2500000", "PRICE_UNIT_MILLI"))],
+        );
+        assert!(!refreshed.price_changed());
+    }
+}
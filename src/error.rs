@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::domain::ApiError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SkyscannerError {
+    #[error("Invalid client configuration: {0}")]
+    Configuration(String),
+    #[error("Failed to reach the Skyscanner API: {0}")]
+    Transport(#[from] reqwest::Error),
+    #[error("The API key was rejected: {0}")]
+    Unauthorized(ErrorBody),
+    #[error("The API key ran out of quota: {body}")]
+    QuotaExceeded {
+        retry_after: Option<Duration>,
+        body: ErrorBody,
+    },
+    #[error("The request was rejected with {status}: {body}")]
+    Validation { status: StatusCode, body: ErrorBody },
+    #[error("The API responded with {status}: {body}")]
+    Status { status: StatusCode, body: ErrorBody },
+    #[error("Failed to deserialize the response: {body}")]
+    Deserialize {
+        #[source]
+        source: serde_json::Error,
+        body: String,
+    },
+    #[error("The search {session_token} failed")]
+    SearchFailed { session_token: String },
+    #[error("The search {session_token} stopped polling before it completed")]
+    SearchIncomplete { session_token: String },
+    #[error("Cassette error: {0}")]
+    Cassette(String),
+    #[error("Cache error: {0}")]
+    Cache(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+}
+
+/// Why an itinerary of a response can't be formatted.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    #[error("Itinerary {itinerary_id} refers to the missing leg {leg_id}")]
+    MissingLeg {
+        itinerary_id: String,
+        leg_id: String,
+    },
+}
+
+/// The body of a failed response, parsed into the API's error shape when possible.
+#[derive(Debug)]
+pub struct ErrorBody {
+    pub raw: String,
+    pub error: Option<ApiError>,
+}
+
+impl SkyscannerError {
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>, body: String) -> Self {
+        let body = ErrorBody::new(body);
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(body),
+            StatusCode::TOO_MANY_REQUESTS => Self::QuotaExceeded { retry_after, body },
+            status if status.is_client_error() => Self::Validation { status, body },
+            status => Self::Status { status, body },
+        }
+    }
+}
+
+impl ErrorBody {
+    pub fn new(raw: String) -> Self {
+        let error = serde_json::from_str::<ApiError>(&raw).ok();
+        Self { raw, error }
+    }
+}
+
+impl Display for ErrorBody {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            Some(error) => write!(f, "{} (code {})", error.message, error.code),
+            None => write!(f, "{}", self.raw),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_works_when_mapping_status_codes() {
+        let body = r#"{"code": 3, "message": "Invalid market", "details": []}"#.to_string();
+
+        let error = SkyscannerError::from_status(StatusCode::BAD_REQUEST, None, body.clone());
+        assert!(matches!(
+            &error,
+            SkyscannerError::Validation { status, body }
+                if *status == StatusCode::BAD_REQUEST
+                    && body.error.as_ref().map(|e| e.message.as_str()) == Some("Invalid market")
+        ));
+
+        let error = SkyscannerError::from_status(StatusCode::UNAUTHORIZED, None, body.clone());
+        assert!(matches!(error, SkyscannerError::Unauthorized(_)));
+
+        let error = SkyscannerError::from_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(Duration::from_secs(3)),
+            "slow down".to_string(),
+        );
+        assert!(matches!(
+            error,
+            SkyscannerError::QuotaExceeded {
+                retry_after: Some(_),
+                body: ErrorBody { error: None, .. }
+            }
+        ));
+
+        let error = SkyscannerError::from_status(StatusCode::BAD_GATEWAY, None, body);
+        assert!(matches!(error, SkyscannerError::Status { .. }));
+    }
+}
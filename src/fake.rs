@@ -0,0 +1,306 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::api::SkyscannerApi;
+use crate::domain::{
+    AutosuggestRequest, AutosuggestResponse, Carriers, CreateFlightsRequest, Currencies, Currency,
+    Details, FightResult, FightsContent, FlightsResponse, FormattedResult, GeoResponse,
+    IndicativeResponse, IndicativeSearchRequest, ItineraryRefreshRequest, ItineraryRefreshResponse,
+    Locale, Locales, Market, Markets, NearestCulture, ResponseAction, ResponseDateTime,
+    ResponseStatus, TripType,
+};
+use crate::error::SkyscannerError;
+use crate::money::Money;
+
+/// An in-memory `SkyscannerApi` serving canned responses, shared between its clones.
+///
+/// Create and poll calls pop the flights responses in the order they were pushed.
+#[derive(Clone, Default)]
+pub struct FakeApi {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    flights: VecDeque<Result<FlightsResponse, SkyscannerError>>,
+    refreshes: VecDeque<Result<ItineraryRefreshResponse, SkyscannerError>>,
+    markets: Vec<Market>,
+    locales: Vec<Locale>,
+    currencies: Vec<Currency>,
+    nearest_culture: Option<NearestCulture>,
+    indicative: Option<IndicativeResponse>,
+    suggestions: AutosuggestResponse,
+    locations: GeoResponse,
+    carriers: Carriers,
+    creates: Vec<CreateFlightsRequest>,
+    polls: Vec<String>,
+}
+
+impl FakeApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_response(self, res: Result<FlightsResponse, SkyscannerError>) -> Self {
+        self.state.lock().unwrap().flights.push_back(res);
+        self
+    }
+
+    /// Queues a response of the itinerary refresh create and poll calls.
+    pub fn push_refresh(self, res: Result<ItineraryRefreshResponse, SkyscannerError>) -> Self {
+        self.state.lock().unwrap().refreshes.push_back(res);
+        self
+    }
+
+    pub fn set_markets(self, markets: Markets) -> Self {
+        self.state.lock().unwrap().markets = markets.markets;
+        self
+    }
+
+    pub fn set_locales(self, locales: Locales) -> Self {
+        self.state.lock().unwrap().locales = locales.locales;
+        self
+    }
+
+    pub fn set_currencies(self, currencies: Currencies) -> Self {
+        self.state.lock().unwrap().currencies = currencies.currencies;
+        self
+    }
+
+    pub fn set_nearest_culture(self, res: NearestCulture) -> Self {
+        self.state.lock().unwrap().nearest_culture = Some(res);
+        self
+    }
+
+    pub fn set_indicative(self, res: IndicativeResponse) -> Self {
+        self.state.lock().unwrap().indicative = Some(res);
+        self
+    }
+
+    pub fn set_suggestions(self, res: AutosuggestResponse) -> Self {
+        self.state.lock().unwrap().suggestions = res;
+        self
+    }
+
+    pub fn set_locations(self, res: GeoResponse) -> Self {
+        self.state.lock().unwrap().locations = res;
+        self
+    }
+
+    pub fn set_carriers(self, res: Carriers) -> Self {
+        self.state.lock().unwrap().carriers = res;
+        self
+    }
+
+    /// Every create request received so far.
+    pub fn creates(&self) -> Vec<CreateFlightsRequest> {
+        self.state.lock().unwrap().creates.clone()
+    }
+
+    /// The session tokens of every poll received so far.
+    pub fn polls(&self) -> Vec<String> {
+        self.state.lock().unwrap().polls.clone()
+    }
+
+    fn next_response(&self) -> Result<FlightsResponse, SkyscannerError> {
+        self.state
+            .lock()
+            .unwrap()
+            .flights
+            .pop_front()
+            .unwrap_or_else(|| {
+                Err(SkyscannerError::Configuration(
+                    "FakeApi ran out of canned responses".to_string(),
+                ))
+            })
+    }
+
+    fn next_refresh(&self) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+        self.state
+            .lock()
+            .unwrap()
+            .refreshes
+            .pop_front()
+            .unwrap_or_else(|| {
+                Err(SkyscannerError::Configuration(
+                    "FakeApi ran out of canned refresh responses".to_string(),
+                ))
+            })
+    }
+}
+
+/// Builds search results with the given itineraries, each without legs and with the same
+/// pricing options, given as `(id, amount in milli-units, update status)`.
+pub fn results(ids: &[&str], options: &[(&str, &str, &str)]) -> FightResult {
+    let options = options
+        .iter()
+        .map(|(option, amount, status)| {
+            serde_json::json!({
+                "id": option,
+                "price": { "amount": amount, "unit": "PRICE_UNIT_MILLI", "updateStatus": status },
+                "agentIds": [],
+                "transferType": "TRANSFER_TYPE_MANAGED"
+            })
+        })
+        .collect::<Vec<_>>();
+    let itineraries = ids
+        .iter()
+        .map(|id| {
+            (
+                id.to_string(),
+                serde_json::json!({ "pricingOptions": options, "legIds": [] }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::from_value(serde_json::json!({ "itineraries": itineraries })).unwrap()
+}
+
+/// Builds a one-way TPE-HKG result on 2023-06-02 departing at `hour`, priced in TWD.
+pub fn formatted(
+    id: &str,
+    price: Option<i64>,
+    hour: u8,
+    minutes: u32,
+    stops: u16,
+) -> FormattedResult {
+    let at = |hour| ResponseDateTime {
+        year: 2023,
+        month: 6,
+        day: 2,
+        hour,
+        minute: 0,
+        second: 0,
+    };
+    FormattedResult {
+        itinerary_id: id.to_string(),
+        leg_ids: vec![format!("{id}-leg")],
+        trip: TripType::OneWay,
+        route: None,
+        price_options: price
+            .map(|e| Money::from_milli(e, "TWD"))
+            .into_iter()
+            .collect(),
+        details: vec![Details {
+            origin: "TPE".to_string(),
+            destination: "HKG".to_string(),
+            departure_date: at(hour),
+            arrival_date: at(hour + 1),
+            duration_in_minutes: minutes,
+            stop_count: stops,
+            segments: vec![],
+            carrier_name: "EVA Air".to_string(),
+        }],
+        currency: Currency::plain("TWD"),
+        booking_url: None,
+    }
+}
+
+/// Builds a response for `FakeApi` without going through JSON.
+pub fn flights_response(
+    session_token: &str,
+    status: ResponseStatus,
+    action: ResponseAction,
+    results: FightResult,
+) -> FlightsResponse {
+    FlightsResponse {
+        session_token: session_token.to_string(),
+        status,
+        action,
+        content: FightsContent { results },
+    }
+}
+
+#[async_trait::async_trait]
+impl SkyscannerApi for FakeApi {
+    async fn get_markets(&self, _locale: &str) -> Result<Markets, SkyscannerError> {
+        Ok(Markets {
+            markets: self.state.lock().unwrap().markets.clone(),
+        })
+    }
+
+    async fn get_locales(&self) -> Result<Locales, SkyscannerError> {
+        Ok(Locales {
+            locales: self.state.lock().unwrap().locales.clone(),
+        })
+    }
+
+    async fn get_currencies(&self) -> Result<Currencies, SkyscannerError> {
+        Ok(Currencies {
+            currencies: self.state.lock().unwrap().currencies.clone(),
+        })
+    }
+
+    async fn get_nearest_culture(
+        &self,
+        _ip_address: Option<&str>,
+    ) -> Result<NearestCulture, SkyscannerError> {
+        self.state
+            .lock()
+            .unwrap()
+            .nearest_culture
+            .clone()
+            .ok_or_else(|| {
+                SkyscannerError::Configuration("FakeApi has no nearest culture".to_string())
+            })
+    }
+
+    async fn create_a_request_to_find_flights(
+        &self,
+        q: &CreateFlightsRequest,
+    ) -> Result<FlightsResponse, SkyscannerError> {
+        self.state.lock().unwrap().creates.push(q.clone());
+        self.next_response()
+    }
+
+    async fn poll_a_request_to_find_flights(
+        &self,
+        token: &str,
+    ) -> Result<FlightsResponse, SkyscannerError> {
+        self.state.lock().unwrap().polls.push(token.to_string());
+        self.next_response()
+    }
+
+    async fn create_itinerary_refresh(
+        &self,
+        _session_token: &str,
+        _q: &ItineraryRefreshRequest,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+        self.next_refresh()
+    }
+
+    async fn poll_itinerary_refresh(
+        &self,
+        _refresh_session_token: &str,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+        self.next_refresh()
+    }
+
+    async fn search_indicative_prices(
+        &self,
+        _q: &IndicativeSearchRequest,
+    ) -> Result<IndicativeResponse, SkyscannerError> {
+        self.state
+            .lock()
+            .unwrap()
+            .indicative
+            .clone()
+            .ok_or_else(|| {
+                SkyscannerError::Configuration("FakeApi has no indicative response".to_string())
+            })
+    }
+
+    async fn autosuggest_flights(
+        &self,
+        _q: &AutosuggestRequest,
+    ) -> Result<AutosuggestResponse, SkyscannerError> {
+        Ok(self.state.lock().unwrap().suggestions.clone())
+    }
+
+    async fn get_flight_locations(&self, _locale: &str) -> Result<GeoResponse, SkyscannerError> {
+        Ok(self.state.lock().unwrap().locations.clone())
+    }
+
+    async fn get_carriers(&self) -> Result<Carriers, SkyscannerError> {
+        Ok(self.state.lock().unwrap().carriers.clone())
+    }
+}
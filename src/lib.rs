@@ -1,5 +1,18 @@
+pub mod api;
+pub mod cache;
+pub mod cassette;
 pub mod configuration;
+pub mod culture;
 pub mod datasource;
 pub mod domain;
+pub mod error;
+#[cfg(test)]
+pub mod fake;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod money;
+pub mod rate_limit;
+pub mod resolver;
+pub mod retry;
 pub mod services;
 pub mod utils;
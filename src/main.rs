@@ -1,22 +1,74 @@
-use chrono::Datelike;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Datelike, Days, NaiveDate, Weekday};
 use clap::value_parser;
-use futures::future::join_all;
-use skyscanner::configuration::get_configuration;
-use skyscanner::datasource::Datasource;
-use skyscanner::domain::{Date, FlightsResponse, Place, Query, QueryLeg};
+use futures::StreamExt;
+use skyscanner::api::SkyscannerApi;
+use skyscanner::cache::ReferenceCache;
+use skyscanner::configuration::{
+    get_configuration, CassetteMode, CassetteSettings, CultureSettings,
+};
+use skyscanner::culture::{
+    detect_culture, Culture, FALLBACK_CURRENCY, FALLBACK_LOCALE, FALLBACK_MARKET,
+};
+use skyscanner::datasource::{prescreen, Datasource, Searched};
+use skyscanner::domain::{
+    Currency, Date, FormattedResult, IndicativeQuery, IndicativeQueryLeg, IndicativeResults,
+    IndicativeSearchRequest, Place, Query, QueryLeg, SortBy,
+};
+use skyscanner::error::SkyscannerError;
+use skyscanner::resolver::{Candidate, PlaceResolver, Resolution};
 use skyscanner::services::Services;
-use skyscanner::utils::{create_dates, parse_input_days};
+use skyscanner::utils::{
+    check_trip_count, create_departure_dates, create_departure_range, is_on_weekdays, pair_dates,
+    parse_date_range, parse_input_days, parse_legs, parse_stay_range, parse_weekday, TripLeg,
+};
 
 #[tokio::main]
 async fn main() {
     let matches = clap::Command::new("raw")
-        .arg(clap::Arg::new("from").long("from").required(true))
-        .arg(clap::Arg::new("to").long("to").required(true))
+        .subcommand_negates_reqs(true)
+        .subcommand(clap::Command::new("list-markets").about("List the markets, named in --locale"))
+        .subcommand(clap::Command::new("list-locales").about("List the locales"))
+        .subcommand(clap::Command::new("list-currencies").about("List the currencies"))
+        .arg(
+            clap::Arg::new("from")
+                .long("from")
+                .help("One or more places, e.g. TPE,TSA,KHH")
+                .value_delimiter(',')
+                .required_unless_present("legs"),
+        )
+        .arg(
+            clap::Arg::new("to")
+                .long("to")
+                .help("One or more places, every origin is searched to every destination")
+                .value_delimiter(',')
+                .required_unless_present("legs"),
+        )
+        .arg(
+            clap::Arg::new("legs")
+                .long("legs")
+                .help(
+                    "Search a multi-city trip instead, e.g. TPE-NRT@2024-06-01,KIX-TPE@2024-06-08",
+                )
+                .value_parser(clap::builder::ValueParser::new(parse_legs))
+                .conflicts_with_all([
+                    "from",
+                    "to",
+                    "year",
+                    "duration",
+                    "one-way",
+                    "months",
+                    "days",
+                    "prescreen",
+                ]),
+        )
         .arg(
             clap::Arg::new("year")
                 .long("year")
                 .value_parser(value_parser!(i32))
-                .required(true),
+                .required_unless_present_any(["legs", "explore", "depart"])
+                .requires_all(["months", "days"]),
         )
         .arg(
             clap::Arg::new("duration")
@@ -24,7 +76,14 @@ async fn main() {
                 .short('d')
                 .value_parser(value_parser!(u64))
                 .value_delimiter(',')
-                .required(true),
+                .required_unless_present_any(["one-way", "legs", "explore", "stay"])
+                .conflicts_with("one-way"),
+        )
+        .arg(
+            clap::Arg::new("one-way")
+                .long("one-way")
+                .help("Search one-way flights, without --duration")
+                .action(clap::ArgAction::SetTrue),
         )
         .arg(
             clap::Arg::new("months")
@@ -33,112 +92,599 @@ async fn main() {
                 .long("months")
                 .value_parser(value_parser!(u16))
                 .value_delimiter(',')
-                .required(true),
+                .required_unless_present_any(["legs", "explore", "depart"])
+                .requires_all(["year", "days"]),
         )
         .arg(
             clap::Arg::new("days")
                 .long("days")
                 .value_parser(clap::builder::ValueParser::new(parse_input_days))
-                .required(true),
+                .required_unless_present_any(["legs", "explore", "depart"])
+                .requires_all(["year", "months"]),
+        )
+        .arg(
+            clap::Arg::new("depart")
+                .long("depart")
+                .help("Depart on any day of a range instead of --year, -m and --days, e.g. 2024-06-01..2024-06-15")
+                .value_parser(clap::builder::ValueParser::new(parse_date_range))
+                .conflicts_with_all(["year", "months", "days", "legs"]),
+        )
+        .arg(
+            clap::Arg::new("stay")
+                .long("stay")
+                .help("Stay for a range of days instead of --duration, e.g. 3..5")
+                .value_parser(clap::builder::ValueParser::new(parse_stay_range))
+                .conflicts_with_all(["duration", "one-way", "legs"]),
+        )
+        .arg(
+            clap::Arg::new("depart-on")
+                .long("depart-on")
+                .help("Only depart on these weekdays, e.g. fri,sat")
+                .value_parser(clap::builder::ValueParser::new(parse_weekday))
+                .value_delimiter(',')
+                .conflicts_with("legs"),
+        )
+        .arg(
+            clap::Arg::new("return-on")
+                .long("return-on")
+                .help("Only come back on these weekdays, e.g. sun,mon")
+                .value_parser(clap::builder::ValueParser::new(parse_weekday))
+                .value_delimiter(',')
+                .conflicts_with_all(["one-way", "legs"]),
         )
         .arg(
             clap::Arg::new("locale")
                 .long("locale")
-                .default_value("zh-TW"),
+                .help("Defaults to `culture.locale`, or the nearest culture of this machine")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("market")
+                .long("market")
+                .help("Defaults to `culture.market`, or the nearest culture of this machine"),
         )
-        .arg(clap::Arg::new("market").long("market").default_value("TW"))
         .arg(
             clap::Arg::new("currency")
                 .long("currency")
-                .default_value("TWD"),
+                .help("Defaults to `culture.currency`, or the currency of the market"),
+        )
+        .arg(
+            clap::Arg::new("explore")
+                .long("explore")
+                .help("Rank the airports of --to, also a country or anywhere, by cached prices; the dates are optional")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all(["legs", "prescreen", "sort"]),
+        )
+        .arg(
+            clap::Arg::new("prescreen")
+                .long("prescreen")
+                .help("Only run live searches for the N date pairs with the cheapest cached prices")
+                .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            clap::Arg::new("sort")
+                .long("sort")
+                .help("The order of the results, the best one is printed last")
+                .value_parser(value_parser!(SortBy))
+                .default_value("price"),
+        )
+        .arg(
+            clap::Arg::new("include-carriers")
+                .long("include-carriers")
+                .help("Only search these carriers, given as IATA codes or carrier ids")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("exclude-carriers")
+                .long("exclude-carriers")
+                .help("Leave out these carriers, given as IATA codes or carrier ids")
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("record")
+                .long("record")
+                .global(true)
+                .help("Record the API traffic to this cassette file")
+                .conflicts_with("replay"),
+        )
+        .arg(
+            clap::Arg::new("replay")
+                .long("replay")
+                .global(true)
+                .help("Replay the API traffic from this cassette file instead of the network"),
         )
         .get_matches();
 
-    let config = get_configuration().expect("Can't get the configuration.");
+    let mut config = get_configuration().expect("Can't get the configuration.");
+
+    if let Some(path) = matches.get_one::<String>("record") {
+        config.cassette = Some(CassetteSettings {
+            mode: CassetteMode::Record,
+            path: path.to_owned(),
+        });
+    } else if let Some(path) = matches.get_one::<String>("replay") {
+        config.cassette = Some(CassetteSettings {
+            mode: CassetteMode::Replay,
+            path: path.to_owned(),
+        });
+    }
 
     let services = Services::new(&config).expect("Can't create a services");
+    // A replay is served by the cassette alone, it neither reads nor writes the cache.
+    let cache = match &config.cassette {
+        Some(cassette) if cassette.mode == CassetteMode::Replay => ReferenceCache::disabled(),
+        _ => ReferenceCache::new(&config.cache, &config.http.base_url),
+    };
 
-    let market = matches
-        .get_one::<String>("market")
-        .map(|e| e.to_uppercase())
-        .expect("Invalid market");
-
-    let currency = matches
-        .get_one::<String>("currency")
-        .map(|e| e.to_uppercase())
-        .expect("Invalid currency");
-
-    let from = matches.get_one::<String>("from").expect("Invalid from");
-    let to = matches.get_one::<String>("to").expect("Invalid to");
-    let locale = matches.get_one::<String>("locale").expect("Invalid locale");
-    let year = matches.get_one::<i32>("year").expect("Invalid year");
-    let months = matches
-        .get_many::<u16>("months")
-        .expect("Invalid months")
-        .copied()
-        .collect::<Vec<_>>();
-    let days = matches
-        .get_one::<Vec<Vec<u16>>>("days")
-        .expect("Invalid days");
-    let durations = matches
-        .get_many::<u64>("duration")
-        .expect("Invalid duration")
-        .copied()
-        .collect::<Vec<_>>();
+    // The command line wins over the settings, the rest is detected.
+    let mut chosen = CultureSettings {
+        market: matches.get_one::<String>("market").cloned(),
+        locale: matches.get_one::<String>("locale").cloned(),
+        currency: matches.get_one::<String>("currency").cloned(),
+    }
+    .or(config.culture.clone());
+    if !chosen.is_complete() {
+        chosen = chosen.or(detect_culture(&services).await);
+    }
 
-    if months.len() != days.len() {
-        panic!("Invalid input, months length should equal days length.");
+    let locale = chosen.locale.unwrap_or_else(|| FALLBACK_LOCALE.to_string());
+    let culture = Culture::load(&services, &cache, &locale).await;
+
+    if let Some((name, _)) = matches.subcommand() {
+        let culture = culture.unwrap_or_else(|e| {
+            eprintln!("Can't load the {}: {e}", name.trim_start_matches("list-"));
+            std::process::exit(1);
+        });
+        match name {
+            "list-markets" => culture
+                .markets
+                .iter()
+                .for_each(|e| println!("{}\t{}", e.code, e.name)),
+            "list-locales" => culture
+                .locales
+                .iter()
+                .for_each(|e| println!("{}\t{}", e.code, e.name)),
+            _ => culture
+                .currencies
+                .iter()
+                .for_each(|e| println!("{}\t{}", e.code, e.symbol)),
+        }
+        return;
     }
 
+    let market = chosen.market.unwrap_or_else(|| FALLBACK_MARKET.to_string());
+    let currency = chosen
+        .currency
+        .or_else(|| {
+            let culture = culture.as_ref().ok()?;
+            culture.market_currency(&market).map(str::to_string)
+        })
+        .unwrap_or_else(|| FALLBACK_CURRENCY.to_string());
+
+    // Without the reference data, e.g. offline, the codes are left for the API to check.
+    let (market, locale, currency) = match &culture {
+        Ok(culture) => {
+            let check = |result: Result<String, SkyscannerError>| {
+                result.unwrap_or_else(|e| {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                })
+            };
+            (
+                check(culture.validate_market(&market)),
+                check(culture.validate_locale(&locale)),
+                check(culture.validate_currency(&currency)),
+            )
+        }
+        Err(e) => {
+            eprintln!("Can't load the markets, locales and currencies: {e}");
+            (market.to_uppercase(), locale, currency.to_uppercase())
+        }
+    };
+    let currency_style = culture
+        .as_ref()
+        .ok()
+        .and_then(|culture| culture.currency(&currency).cloned())
+        .unwrap_or_else(|| Currency::plain(&currency));
+
     let mut q = Query::default();
     q = q.set_market(market);
     q = q.set_currency(currency);
-    q = q.set_locale(locale.to_owned());
+    q = q.set_locale(locale);
+
+    let carriers = cache
+        .get_or_fetch("carriers", || services.get_carriers())
+        .await
+        .map_err(|e| eprintln!("Can't load the carriers: {e}"))
+        .ok();
+    let codes = |name: &str| {
+        matches
+            .get_many::<String>(name)
+            .unwrap_or_default()
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    q = q
+        .set_carriers(
+            &codes("include-carriers"),
+            &codes("exclude-carriers"),
+            carriers.as_ref(),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(1);
+        });
+    let carriers = carriers.unwrap_or_default();
 
-    let from = Place::new(Some(from.to_owned()), None);
-    let to = Place::new(Some(to.to_owned()), None);
+    if matches.get_flag("explore") {
+        explore(&matches, &services, &q, &currency_style).await;
+        return;
+    }
 
-    let dates = create_dates(*year, months, days, durations);
+    let resolver = PlaceResolver::new(services.clone(), q.market.clone(), q.locale.clone());
+    let queries = match matches.get_one::<Vec<TripLeg>>("legs") {
+        Some(legs) => vec![multi_city_query(&resolver, q, legs).await],
+        None => date_queries(&matches, &services, &resolver, q, config.search.max_trips).await,
+    };
+    let routes = queries
+        .iter()
+        .map(|(route, _)| route.as_str())
+        .collect::<HashSet<_>>()
+        .len();
 
-    let mut data_sources = dates
+    let data_sources = queries
         .into_iter()
-        .map(|(s, e)| {
+        .map(|(route, query)| {
             (
-                Date::new(s.year(), s.month(), s.day()),
-                Date::new(e.year(), e.month(), e.day()),
+                route,
+                Datasource::new(query, services.clone(), config.search.clone()),
             )
         })
-        .map(|(s, e)| {
-            let mut q = q.clone();
-            let leg = QueryLeg::new(from.clone(), to.clone(), s);
-            q = q.set_query_leg(leg);
-            let leg = QueryLeg::new(to.clone(), from.clone(), e);
-            q = q.set_query_leg(leg);
-            q
+        .collect::<Vec<_>>();
+
+    let result: Vec<(String, Searched)> = futures::stream::iter(data_sources)
+        .map(|(route, datasource)| async move { (route, datasource.search().await) })
+        .buffer_unordered(config.search.max_concurrent_searches.max(1))
+        .collect()
+        .await;
+
+    let response = result
+        .into_iter()
+        .map(|(route, searched)| {
+            if let Some(e) = &searched.incomplete {
+                eprintln!("Search ended early, keeping what it found: {e:#}");
+            }
+            (route, searched.results)
         })
-        .map(|query| Datasource::new(query, services.clone()))
+        .flat_map(|(route, mut e)| {
+            e.fill_carriers(&carriers);
+            e.format(&currency_style)
+                .into_iter()
+                .map(move |e| e.map(|e| tag_route(e, &route, routes)))
+        })
+        .filter_map(|e| e.map_err(|e| eprintln!("Skipped: {e}")).ok())
+        .collect::<Vec<_>>();
+    let sort_by = *matches.get_one::<SortBy>("sort").unwrap();
+    let mut response = FormattedResult::dedup(response);
+    response.sort_by(|a, b| sort_by.compare(a, b));
+
+    response.iter().rev().for_each(|f| println!("{}", f));
+
+    if routes > 1 {
+        let cheapest = FormattedResult::cheapest_by_route(&response);
+        println!("Cheapest per route:");
+        for (route, result) in cheapest.iter() {
+            println!("{route}:\t{}", price(result));
+        }
+        if let Some((route, result)) = cheapest
+            .iter()
+            .min_by(|(_, a), (_, b)| SortBy::Price.compare(a, b))
+        {
+            println!("Cheapest overall: {route} {}", price(result));
+        }
+    }
+}
+
+/// Only tells the routes apart when several were searched.
+fn tag_route(mut result: FormattedResult, route: &str, routes: usize) -> FormattedResult {
+    if routes > 1 {
+        result.route = Some(route.to_string());
+    }
+    result
+}
+
+fn price(result: &FormattedResult) -> String {
+    result
+        .price_options
+        .first()
+        .map(|e| e.format(&result.currency))
+        .unwrap_or_default()
+}
+
+/// Resolves `--from` or `--to`, asking on the terminal when the input matches several places.
+async fn resolve_place(resolver: &PlaceResolver<Services>, input: &str) -> Place {
+    match resolver.resolve(input).await {
+        Ok(Resolution::Resolved(place)) => place,
+        Ok(Resolution::Ambiguous(candidates)) => choose(input, &candidates).to_place(),
+        Ok(Resolution::NotFound) => {
+            eprintln!("Can't find a place matching \"{input}\".");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Can't look up \"{input}\": {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn choose<'a>(input: &str, candidates: &'a [Candidate]) -> &'a Candidate {
+    eprintln!("\"{input}\" matches several places:");
+    for (i, candidate) in candidates.iter().enumerate() {
+        eprintln!("  {}) {candidate}", i + 1);
+    }
+
+    loop {
+        eprint!("Pick one [1-{}]: ", candidates.len());
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            eprintln!();
+            std::process::exit(1);
+        }
+        match line.trim().parse::<usize>() {
+            Ok(i) if (1..=candidates.len()).contains(&i) => return &candidates[i - 1],
+            _ => eprintln!("Invalid choice \"{}\".", line.trim()),
+        }
+    }
+}
+
+/// One query per route and trip of the `--from`/`--to` and date options, with its route.
+async fn date_queries(
+    matches: &clap::ArgMatches,
+    services: &Services,
+    resolver: &PlaceResolver<Services>,
+    q: Query,
+    max_trips: usize,
+) -> Vec<(String, Query)> {
+    let from = matches
+        .get_many::<String>("from")
+        .expect("Invalid from")
         .collect::<Vec<_>>();
+    let to = matches
+        .get_many::<String>("to")
+        .expect("Invalid to")
+        .collect::<Vec<_>>();
+    let places = resolve_places(resolver, from.iter().chain(to.iter()).map(|e| e.as_str())).await;
+    // Inputs resolving to the same place, e.g. `TPE` given twice or two spellings of the same
+    // city, are searched once. An airport and its city, e.g. `TPE` and `Taipei`, stay apart.
+    let distinct = |inputs: &[&String]| {
+        let mut distinct: Vec<(String, Place)> = vec![];
+        for input in inputs {
+            let place = &places[input.as_str()];
+            if distinct.iter().all(|(_, e)| e != place) {
+                distinct.push((input.to_string(), place.clone()));
+            }
+        }
+        distinct
+    };
+    let (from, to) = (distinct(&from), distinct(&to));
+    let routes = from
+        .iter()
+        .flat_map(|from| to.iter().map(move |to| (from, to)))
+        .filter(|((_, from), (_, to))| from != to)
+        .collect::<Vec<_>>();
+    let dates = trips(matches);
+    // Pre-screening already keeps only the cheapest few trips of every route.
+    if !matches.contains_id("prescreen") {
+        if let Err(e) = check_trip_count(routes.len() * dates.len(), max_trips) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
 
-    let mut result = vec![];
+    let mut queries = vec![];
+    for ((from, from_place), (to, to_place)) in routes {
+        let route = format!("{from}-{to}");
+        let (from, to) = (from_place, to_place);
 
-    for _ in 0..2 {
-        let tasks = data_sources
-            .iter_mut()
-            .map(|e| e.next())
-            .collect::<Vec<_>>();
+        let mut dates = dates.clone();
+        if let Some(limit) = matches.get_one::<usize>("prescreen") {
+            let total = dates.len();
+            dates = prescreen(services, &q, from, to, dates, *limit).await;
+            eprintln!("Pre-screened {} of {total} trips of {route}.", dates.len());
+        }
 
-        let res: Vec<anyhow::Result<Option<FlightsResponse>>> = join_all(tasks).await;
-        result.extend(res);
-        std::thread::sleep(std::time::Duration::from_secs(10));
+        queries.extend(dates.into_iter().map(|(s, e)| {
+            let mut q = q.clone();
+            let leg = QueryLeg::new(from.clone(), to.clone(), date(s));
+            q = q.set_query_leg(leg);
+            if let Some(e) = e {
+                let leg = QueryLeg::new(to.clone(), from.clone(), date(e));
+                q = q.set_query_leg(leg);
+            }
+            (route.clone(), q)
+        }));
     }
+    queries
+}
 
-    let mut response = result
+/// Resolves every distinct input once, so an ambiguous one is only asked about once.
+async fn resolve_places<'a>(
+    resolver: &PlaceResolver<Services>,
+    inputs: impl Iterator<Item = &'a str>,
+) -> HashMap<&'a str, Place> {
+    let mut places = HashMap::new();
+    for input in inputs {
+        if !places.contains_key(input) {
+            places.insert(input, resolve_place(resolver, input).await);
+        }
+    }
+    places
+}
+
+/// The departure and, unless it's one-way, return days of the date options, either
+/// `--depart`/`--stay` or `--year`/`-m`/`--days`/`--duration`, on the chosen weekdays.
+fn trips(matches: &clap::ArgMatches) -> Vec<(NaiveDate, Option<NaiveDate>)> {
+    let departures = departures(matches);
+    // A one-way trip has no return day.
+    if matches.get_flag("one-way") {
+        return departures.into_iter().map(|s| (s, None)).collect();
+    }
+
+    let return_on = weekdays(matches, "return-on");
+    pair_dates(departures, &stays(matches))
+        .into_iter()
+        .filter(|(_, e)| is_on_weekdays(*e, &return_on))
+        .map(|(s, e)| (s, Some(e)))
+        .collect()
+}
+
+/// The departure days of the date options on the `--depart-on` weekdays, none without them,
+/// e.g. when exploring.
+fn departures(matches: &clap::ArgMatches) -> Vec<NaiveDate> {
+    let departures = match (
+        matches.get_one::<(NaiveDate, NaiveDate)>("depart"),
+        matches.get_one::<i32>("year"),
+        matches.get_many::<u16>("months"),
+        matches.get_one::<Vec<Vec<u16>>>("days"),
+    ) {
+        (Some((start, end)), _, _, _) => create_departure_range(*start, *end),
+        (None, Some(year), Some(months), Some(days)) => {
+            let months = months.copied().collect::<Vec<_>>();
+            if months.len() != days.len() {
+                panic!("Invalid input, months length should equal days length.");
+            }
+            create_departure_dates(*year, months, days)
+        }
+        _ => vec![],
+    };
+
+    let depart_on = weekdays(matches, "depart-on");
+    departures
         .into_iter()
-        .flatten()
-        .flatten()
-        .flat_map(|e| e.content.results.format())
+        .filter(|s| is_on_weekdays(*s, &depart_on))
+        .collect()
+}
+
+/// The stays in days, of `--stay` or `--duration`.
+fn stays(matches: &clap::ArgMatches) -> Vec<u64> {
+    match matches.get_one::<Vec<u64>>("stay") {
+        Some(stays) => stays.clone(),
+        None => matches
+            .get_many::<u64>("duration")
+            .unwrap_or_default()
+            .copied()
+            .collect(),
+    }
+}
+
+fn weekdays(matches: &clap::ArgMatches, name: &str) -> Vec<Weekday> {
+    matches
+        .get_many::<Weekday>(name)
+        .unwrap_or_default()
+        .copied()
+        .collect()
+}
+
+/// A single query flying the legs in order, with the legs as its route.
+async fn multi_city_query(
+    resolver: &PlaceResolver<Services>,
+    q: Query,
+    legs: &[TripLeg],
+) -> (String, Query) {
+    let places = resolve_places(
+        resolver,
+        legs.iter()
+            .flat_map(|leg| [leg.from.as_str(), leg.to.as_str()]),
+    )
+    .await;
+
+    let route = legs
+        .iter()
+        .map(|leg| format!("{}-{}", leg.from, leg.to))
+        .collect::<Vec<_>>()
+        .join(",");
+    let q = legs.iter().fold(q, |q, leg| {
+        q.set_query_leg(QueryLeg::new(
+            places[leg.from.as_str()].clone(),
+            places[leg.to.as_str()].clone(),
+            date(leg.date),
+        ))
+    });
+    (route, q)
+}
+
+/// Ranks the destination airports of `--to`, a city, a country or anywhere, by the cheapest
+/// indicative quote from any of the origins, in the months of the date options, or anytime
+/// without them.
+async fn explore(matches: &clap::ArgMatches, services: &Services, q: &Query, currency: &Currency) {
+    let resolver =
+        PlaceResolver::new(services.clone(), q.market.clone(), q.locale.clone()).with_countries();
+    let from = matches
+        .get_many::<String>("from")
+        .expect("Invalid from")
+        .map(|e| e.as_str())
         .collect::<Vec<_>>();
-    response.sort();
+    let origins = resolve_places(&resolver, from.iter().copied()).await;
+    let mut destinations = vec![];
+    for to in matches.get_many::<String>("to").expect("Invalid to") {
+        destinations.push(if to.eq_ignore_ascii_case("anywhere") {
+            Place::anywhere()
+        } else {
+            resolve_place(&resolver, to).await
+        });
+    }
 
-    response.iter().rev().for_each(|f| println!("{}", f));
+    // The outbound range covers the departure days, the inbound one the longest stay too.
+    let departures = departures(matches);
+    let longest_stay = stays(matches).into_iter().max().unwrap_or_default();
+    let range = |back: bool| {
+        let first = *departures.iter().min()?;
+        let last = *departures.iter().max()?;
+        let last = if back {
+            last.checked_add_days(Days::new(longest_stay))
+                .unwrap_or(last)
+        } else {
+            last
+        };
+        Some((first, last))
+    };
+    let leg = |from: &Place, to: &Place, back: bool| match range(back) {
+        Some((first, last)) => {
+            IndicativeQueryLeg::month_range(from.clone(), to.clone(), first.into(), last.into())
+        }
+        None => IndicativeQueryLeg::anytime(from.clone(), to.clone()),
+    };
+
+    let mut results = IndicativeResults::default();
+    for input in from.iter() {
+        let origin = &origins[input];
+        for destination in destinations.iter() {
+            let mut query =
+                IndicativeQuery::new(q.market.clone(), q.locale.clone(), q.currency.clone())
+                    .set_query_leg(leg(origin, destination, false));
+            if !matches.get_flag("one-way") {
+                query = query.set_query_leg(leg(destination, origin, true));
+            }
+
+            match services
+                .search_indicative_prices(&IndicativeSearchRequest::new(query))
+                .await
+            {
+                Ok(res) => results.merge(res.content.results),
+                Err(e) => eprintln!("Explore search from {input} failed: {e}"),
+            }
+        }
+    }
+
+    let destinations = results.cheapest_destinations(currency);
+    if destinations.is_empty() {
+        eprintln!("No cached prices found.");
+    }
+    for (i, destination) in destinations.iter().enumerate() {
+        println!("{}. {destination}", i + 1);
+    }
+}
+
+fn date(date: NaiveDate) -> Date {
+    Date::new(date.year(), date.month(), date.day())
 }
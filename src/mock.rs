@@ -0,0 +1,1175 @@
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Timelike};
+
+use crate::domain::{
+    Agent, AgentType, ApiError, AutosuggestRequest, AutosuggestResponse, Carrier, Carriers,
+    CreateFlightsRequest, Currencies, Currency, FightResult, FightsContent, FlightsResponse,
+    GeoResponse, IndicativeContent, IndicativeResponse, IndicativeResults, IndicativeSearchRequest,
+    Itinerary, ItineraryRefreshRequest, ItineraryRefreshResponse, Leg, Locale, Locales, Market,
+    Markets, NearestCulture, Place, PlaceType, Price, PriceOption, PricingItem, Query, Quote,
+    QuoteLeg, ResponseAction, ResponseDateTime, ResponsePlace, ResponseStatus, Segment,
+    SuggestedPlace,
+};
+
+/// Any leg from or to this code makes the search end with `RESULT_STATUS_FAILED`.
+pub const FAILING_IATA: &str = "ERR";
+
+// (code, name, currency)
+const MARKETS: [(&str, &str, &str); 5] = [
+    ("TW", "Taiwan", "TWD"),
+    ("HK", "Hong Kong", "HKD"),
+    ("JP", "Japan", "JPY"),
+    ("UK", "United Kingdom", "GBP"),
+    ("US", "United States", "USD"),
+];
+
+const LOCALES: [(&str, &str); 5] = [
+    ("zh-TW", "Chinese (Traditional)"),
+    ("zh-HK", "Chinese (Hong Kong)"),
+    ("ja-JP", "Japanese"),
+    ("en-GB", "English (United Kingdom)"),
+    ("en-US", "English (United States)"),
+];
+
+// (code, symbol, decimal digits)
+const CURRENCIES: [(&str, &str, u32); 5] = [
+    ("TWD", "NT$", 0),
+    ("HKD", "HK$", 2),
+    ("JPY", "¥", 0),
+    ("GBP", "£", 2),
+    ("USD", "$", 2),
+];
+
+// (carrier id, name, iata)
+const CARRIERS: [(&str, &str, &str); 4] = [
+    ("-32222", "Cathay Pacific", "CX"),
+    ("-32166", "China Airlines", "CI"),
+    ("-31736", "EVA Air", "BR"),
+    ("-30648", "STARLUX Airlines", "JX"),
+];
+
+// (entity id, parent id, name, iata, type)
+type MockPlace = (
+    &'static str,
+    &'static str,
+    &'static str,
+    &'static str,
+    PlaceType,
+);
+
+const PLACES: [MockPlace; 16] = [
+    ("29475402", "", "Taiwan", "", PlaceType::PlaceTypeCountry),
+    (
+        "27547373",
+        "29475402",
+        "Taipei",
+        "",
+        PlaceType::PlaceTypeCity,
+    ),
+    (
+        "95673372",
+        "27547373",
+        "Taipei Taoyuan",
+        "TPE",
+        PlaceType::PlaceTypeAirport,
+    ),
+    (
+        "128667080",
+        "27547373",
+        "Taipei Songshan",
+        "TSA",
+        PlaceType::PlaceTypeAirport,
+    ),
+    (
+        "29475384",
+        "",
+        "Hong Kong SAR",
+        "",
+        PlaceType::PlaceTypeCountry,
+    ),
+    (
+        "27546079",
+        "29475384",
+        "Hong Kong",
+        "",
+        PlaceType::PlaceTypeCity,
+    ),
+    (
+        "95673827",
+        "27546079",
+        "Hong Kong International",
+        "HKG",
+        PlaceType::PlaceTypeAirport,
+    ),
+    ("29475330", "", "Japan", "", PlaceType::PlaceTypeCountry),
+    (
+        "27542089",
+        "29475330",
+        "Tokyo",
+        "",
+        PlaceType::PlaceTypeCity,
+    ),
+    (
+        "95673444",
+        "27542089",
+        "Tokyo Narita",
+        "NRT",
+        PlaceType::PlaceTypeAirport,
+    ),
+    (
+        "95673449",
+        "27542089",
+        "Tokyo Haneda",
+        "HND",
+        PlaceType::PlaceTypeAirport,
+    ),
+    (
+        "27542908",
+        "29475330",
+        "Osaka",
+        "",
+        PlaceType::PlaceTypeCity,
+    ),
+    (
+        "95673826",
+        "27542908",
+        "Osaka Kansai",
+        "KIX",
+        PlaceType::PlaceTypeAirport,
+    ),
+    ("29475410", "", "Thailand", "", PlaceType::PlaceTypeCountry),
+    (
+        "27536671",
+        "29475410",
+        "Bangkok",
+        "",
+        PlaceType::PlaceTypeCity,
+    ),
+    (
+        "95673671",
+        "27536671",
+        "Bangkok Suvarnabhumi",
+        "BKK",
+        PlaceType::PlaceTypeAirport,
+    ),
+];
+
+const HUB_IATA: &str = "ICN";
+
+// Where a city, country or anywhere destination of an indicative search flies to.
+const EXPLORE_IATAS: [&str; 3] = ["NRT", "KIX", "BKK"];
+const MOCK_BOOKING_URL: &str = "https://mock.example.com/book";
+
+/// Behaviour of the mock Skyscanner server.
+#[derive(Clone, Debug)]
+pub struct MockSettings {
+    /// Polls needed before a search reports `RESULT_STATUS_COMPLETE`, zero completes on create.
+    pub polls_until_complete: u32,
+    /// Share of requests answered with a `429` or a `503`, between 0 and 1.
+    pub failure_rate: f64,
+    /// Number of itineraries of a complete search.
+    pub itineraries: usize,
+    /// The only accepted `x-api-key`, any non-empty key is accepted when unset.
+    pub api_key: Option<String>,
+}
+
+struct MockState {
+    settings: MockSettings,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+struct Session {
+    query: Query,
+    polls: u32,
+    reported: Option<usize>,
+}
+
+impl Default for MockSettings {
+    fn default() -> Self {
+        Self {
+            polls_until_complete: 2,
+            failure_rate: 0.0,
+            itineraries: 10,
+            api_key: None,
+        }
+    }
+}
+
+/// The routes of the mock server, mounted under the same `/apiservices/v3/` prefix as the API.
+pub fn app(settings: MockSettings) -> Router {
+    let state = Arc::new(MockState {
+        settings,
+        sessions: Mutex::new(HashMap::new()),
+    });
+
+    Router::new()
+        .route("/apiservices/v3/culture/markets/:locale", get(markets))
+        .route("/apiservices/v3/culture/locales", get(locales))
+        .route("/apiservices/v3/culture/currencies", get(currencies))
+        .route(
+            "/apiservices/v3/culture/nearestculture",
+            get(nearest_culture),
+        )
+        .route("/apiservices/v3/flights/carriers", get(carriers))
+        .route("/apiservices/v3/autosuggest/flights", post(autosuggest))
+        .route(
+            "/apiservices/v3/geo/hierarchy/flights/:locale",
+            get(locations),
+        )
+        .route("/apiservices/v3/flights/live/search/create", post(create))
+        .route(
+            "/apiservices/v3/flights/live/search/poll/:token",
+            post(poll),
+        )
+        .route(
+            "/apiservices/v3/flights/live/itineraryrefresh/create/:token",
+            post(refresh_create),
+        )
+        .route(
+            "/apiservices/v3/flights/live/itineraryrefresh/poll/:token",
+            get(refresh_poll),
+        )
+        .route(
+            "/apiservices/v3/flights/indicative/search",
+            post(indicative),
+        )
+        .with_state(state)
+}
+
+pub async fn serve(listener: TcpListener, settings: MockSettings) -> anyhow::Result<()> {
+    axum::Server::from_tcp(listener)?
+        .serve(app(settings).into_make_service())
+        .await?;
+    Ok(())
+}
+
+async fn markets(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Path(_locale): Path<String>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let markets = MARKETS
+        .iter()
+        .map(|(code, name, currency)| Market {
+            code: code.to_string(),
+            name: name.to_string(),
+            currency: currency.to_string(),
+        })
+        .collect();
+    Json(Markets { markets }).into_response()
+}
+
+async fn locales(State(state): State<Arc<MockState>>, headers: HeaderMap) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let locales = LOCALES
+        .iter()
+        .map(|(code, name)| Locale {
+            code: code.to_string(),
+            name: name.to_string(),
+        })
+        .collect();
+    Json(Locales { locales }).into_response()
+}
+
+async fn currencies(State(state): State<Arc<MockState>>, headers: HeaderMap) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let currencies = CURRENCIES.iter().map(currency).collect();
+    Json(Currencies { currencies }).into_response()
+}
+
+/// Every caller is placed in the first market, with its locale and currency.
+async fn nearest_culture(State(state): State<Arc<MockState>>, headers: HeaderMap) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let (code, name, currency_code) = MARKETS[0];
+    let (locale_code, locale_name) = LOCALES[0];
+    Json(NearestCulture {
+        market: Market {
+            code: code.to_string(),
+            name: name.to_string(),
+            currency: currency_code.to_string(),
+        },
+        locale: Locale {
+            code: locale_code.to_string(),
+            name: locale_name.to_string(),
+        },
+        currency: currency(&CURRENCIES[0]),
+    })
+    .into_response()
+}
+
+fn currency((code, symbol, decimal_digits): &(&str, &str, u32)) -> Currency {
+    Currency {
+        code: code.to_string(),
+        symbol: symbol.to_string(),
+        thousands_separator: ",".to_string(),
+        decimal_separator: ".".to_string(),
+        symbol_on_left: true,
+        space_between_amount_and_symbol: false,
+        decimal_digits: *decimal_digits,
+    }
+}
+
+async fn carriers(State(state): State<Arc<MockState>>, headers: HeaderMap) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let carriers = CARRIERS
+        .iter()
+        .map(|(id, name, iata)| {
+            (
+                id.to_string(),
+                Carrier {
+                    name: name.to_string(),
+                    alliance_id: "".to_string(),
+                    image_url: "".to_string(),
+                    iata: iata.to_string(),
+                },
+            )
+        })
+        .collect();
+    Json(Carriers { carriers }).into_response()
+}
+
+/// Places of the given types whose name contains the search term, up to the limit.
+async fn autosuggest(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(req): Json<AutosuggestRequest>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let needle = req.query.search_term.to_lowercase();
+    let places = PLACES
+        .iter()
+        .filter(|(_, _, name, _, kind)| {
+            req.query.included_entity_types.contains(kind) && name.to_lowercase().contains(&needle)
+        })
+        .take(req.limit as usize)
+        .map(|(entity_id, _, name, iata, kind)| {
+            let ancestors = ancestors(entity_id);
+            let named = |kind: PlaceType| {
+                ancestors
+                    .iter()
+                    .find(|(_, _, _, _, e)| *e == kind)
+                    .map(|(_, _, name, _, _)| name.to_string())
+                    .unwrap_or_default()
+            };
+            SuggestedPlace {
+                entity_id: entity_id.to_string(),
+                iata_code: iata.to_string(),
+                name: name.to_string(),
+                country_name: named(PlaceType::PlaceTypeCountry),
+                city_name: named(PlaceType::PlaceTypeCity),
+                hierarchy: ancestors
+                    .iter()
+                    .skip(1)
+                    .map(|(_, _, name, _, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                kind: kind.clone(),
+            }
+        })
+        .collect();
+    Json(AutosuggestResponse { places }).into_response()
+}
+
+async fn locations(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Path(_locale): Path<String>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let places = PLACES
+        .iter()
+        .map(|(entity_id, parent_id, name, iata, kind)| {
+            (
+                entity_id.to_string(),
+                ResponsePlace {
+                    entity_id: entity_id.to_string(),
+                    parent_id: parent_id.to_string(),
+                    name: name.to_string(),
+                    iata: iata.to_string(),
+                    kind: kind.clone(),
+                    coordinates: None,
+                },
+            )
+        })
+        .collect();
+    Json(GeoResponse { places }).into_response()
+}
+
+async fn create(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(req): Json<CreateFlightsRequest>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let query = req.query;
+    if !MARKETS.iter().any(|(code, _, _)| *code == query.market) {
+        return api_error(StatusCode::BAD_REQUEST, 3, "Invalid market");
+    }
+    if query.query_legs.is_empty() {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            3,
+            "At least one query leg is required",
+        );
+    }
+    let unknown_place = query.query_legs.iter().any(|leg| {
+        airport(&leg.origin_place_id).is_none() || airport(&leg.destination_place_id).is_none()
+    });
+    if unknown_place {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            3,
+            "Every query leg needs an iata code or a known entity id",
+        );
+    }
+    if query
+        .query_legs
+        .iter()
+        .any(|leg| leg.date.to_naive_date().is_none())
+    {
+        return api_error(StatusCode::BAD_REQUEST, 3, "Invalid date");
+    }
+
+    let mut sessions = state.sessions.lock().unwrap();
+    let token = format!("mock-session-{}", sessions.len() + 1);
+    let session = sessions.entry(token.clone()).or_insert(Session {
+        query,
+        polls: 0,
+        reported: None,
+    });
+    Json(state.respond(&token, session)).into_response()
+}
+
+async fn poll(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let mut sessions = state.sessions.lock().unwrap();
+    match sessions.get_mut(&token) {
+        None => api_error(StatusCode::NOT_FOUND, 5, "Session not found"),
+        Some(session) => {
+            session.polls += 1;
+            Json(state.respond(&token, session)).into_response()
+        }
+    }
+}
+
+async fn refresh_create(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+    Json(req): Json<ItineraryRefreshRequest>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    // The refresh token carries the search and the itinerary, polls need no extra state.
+    state.refresh(&format!("{token}:{}", req.itinerary_id))
+}
+
+async fn refresh_poll(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    state.refresh(&token)
+}
+
+async fn indicative(
+    State(state): State<Arc<MockState>>,
+    headers: HeaderMap,
+    Json(req): Json<IndicativeSearchRequest>,
+) -> Response {
+    if let Some(res) = state.reject(&headers) {
+        return res;
+    }
+
+    let query = req.query;
+    if !MARKETS.iter().any(|(code, _, _)| *code == query.market) {
+        return api_error(StatusCode::BAD_REQUEST, 3, "Invalid market");
+    }
+    let Some(leg) = query.query_legs.first() else {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            3,
+            "At least one query leg is required",
+        );
+    };
+    let Some(origin) = airport(&leg.origin_place.query_place) else {
+        return api_error(
+            StatusCode::BAD_REQUEST,
+            3,
+            "The origin needs an iata code or a known entity id",
+        );
+    };
+    let destination = &leg.destination_place.query_place;
+    let destinations = match (destination.iata(), destination.entity_id()) {
+        (Some(iata), _) => vec![iata],
+        (None, Some(entity_id)) => airports(entity_id),
+        (None, None) => EXPLORE_IATAS.to_vec(),
+    };
+    if destinations.is_empty() {
+        return api_error(StatusCode::BAD_REQUEST, 3, "Unknown destination");
+    }
+
+    let days = if let Some(range) = &leg.date_range {
+        let start = NaiveDate::from_ymd_opt(range.start_date.year, range.start_date.month, 1);
+        let end = NaiveDate::from_ymd_opt(range.end_date.year, range.end_date.month, 1)
+            .and_then(|e| e.checked_add_months(Months::new(1)));
+        match (start, end) {
+            (Some(start), Some(end)) => start.iter_days().take_while(|e| *e < end).collect(),
+            _ => return api_error(StatusCode::BAD_REQUEST, 3, "Invalid date range"),
+        }
+    } else if let Some(date) = leg.fixed_date.as_ref().and_then(|e| e.to_naive_date()) {
+        vec![date]
+    } else {
+        today().iter_days().take(60).collect::<Vec<_>>()
+    };
+
+    let mut results = IndicativeResults::default();
+    for destination in destinations {
+        results.merge(quote(
+            origin,
+            destination,
+            query.query_legs.len() > 1,
+            &days,
+        ));
+    }
+
+    Json(IndicativeResponse {
+        status: ResponseStatus::ResultStatusComplete,
+        content: IndicativeContent { results },
+    })
+    .into_response()
+}
+
+impl MockState {
+    /// Answers with `401` for a missing or wrong key, or with an injected failure.
+    fn reject(&self, headers: &HeaderMap) -> Option<Response> {
+        let key = headers
+            .get("x-api-key")
+            .and_then(|e| e.to_str().ok())
+            .unwrap_or_default();
+        let accepted = match &self.settings.api_key {
+            Some(api_key) => key == api_key,
+            None => !key.is_empty(),
+        };
+        if !accepted {
+            return Some(api_error(StatusCode::UNAUTHORIZED, 16, "Invalid API key"));
+        }
+
+        if rand::random::<f64>() < self.settings.failure_rate {
+            return if rand::random::<bool>() {
+                let mut res = api_error(StatusCode::TOO_MANY_REQUESTS, 8, "Rate limit exceeded");
+                res.headers_mut()
+                    .insert("retry-after", "1".parse().expect("Valid header"));
+                Some(res)
+            } else {
+                Some(api_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    14,
+                    "Service unavailable",
+                ))
+            };
+        }
+
+        None
+    }
+
+    /// Every poll reveals more itineraries until the search completes; a poll revealing
+    /// nothing new is answered with `RESULT_ACTION_NOT_MODIFIED` and no content.
+    /// Refreshes complete at once, with the prices of the complete search.
+    fn refresh(&self, refresh_token: &str) -> Response {
+        let Some((token, itinerary_id)) = refresh_token.split_once(':') else {
+            return api_error(StatusCode::NOT_FOUND, 5, "Refresh session not found");
+        };
+        let sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get(token) else {
+            return api_error(StatusCode::NOT_FOUND, 5, "Session not found");
+        };
+
+        let mut results = synthesize(&session.query, self.settings.itineraries);
+        results.itineraries.retain(|id, _| id == itinerary_id);
+        Json(ItineraryRefreshResponse {
+            refresh_session_token: refresh_token.to_string(),
+            status: ResponseStatus::ResultStatusComplete,
+            action: ResponseAction::ResultActionReplaced,
+            content: FightsContent { results },
+        })
+        .into_response()
+    }
+
+    fn respond(&self, token: &str, session: &mut Session) -> FlightsResponse {
+        let failing = session.query.query_legs.iter().any(|leg| {
+            leg.origin_place_id.iata() == Some(FAILING_IATA)
+                || leg.destination_place_id.iata() == Some(FAILING_IATA)
+        });
+        let complete = session.polls >= self.settings.polls_until_complete;
+        let status = match (failing, complete) {
+            (true, _) if session.polls > 0 => ResponseStatus::ResultStatusFailed,
+            (false, true) => ResponseStatus::ResultStatusComplete,
+            _ => ResponseStatus::ResultStatusIncomplete,
+        };
+
+        let total = self.settings.itineraries;
+        let count = if complete {
+            total
+        } else {
+            let steps = self.settings.polls_until_complete as usize + 1;
+            (total * (session.polls as usize + 1) / steps)
+                .max(1)
+                .min(total)
+        };
+
+        let (action, results) = if session.reported == Some(count) {
+            (
+                ResponseAction::ResultActionNotModified,
+                FightResult::default(),
+            )
+        } else {
+            session.reported = Some(count);
+            (
+                ResponseAction::ResultActionReplaced,
+                synthesize(&session.query, count),
+            )
+        };
+
+        FlightsResponse {
+            session_token: token.to_string(),
+            status,
+            action,
+            content: FightsContent { results },
+        }
+    }
+}
+
+/// The airport a place flies from, a city or a country flies from its first airport. Any
+/// iata code is accepted, entity ids have to be known.
+fn airport(place: &Place) -> Option<&str> {
+    match (place.iata(), place.entity_id()) {
+        (Some(iata), _) => Some(iata),
+        (None, Some(entity_id)) => airports(entity_id).first().copied(),
+        (None, None) => None,
+    }
+}
+
+/// The iata codes of the airports at or below `entity_id`.
+fn airports(entity_id: &str) -> Vec<&'static str> {
+    PLACES
+        .iter()
+        .filter(|(id, _, _, iata, _)| {
+            !iata.is_empty() && ancestors(id).iter().any(|(e, _, _, _, _)| *e == entity_id)
+        })
+        .map(|(_, _, _, iata, _)| *iata)
+        .collect()
+}
+
+/// The place itself, then its parent and so on up to its country.
+fn ancestors(entity_id: &str) -> Vec<&'static MockPlace> {
+    let mut ancestors = vec![];
+    let mut id = entity_id;
+    while let Some(place) = PLACES.iter().find(|(e, _, _, _, _)| *e == id) {
+        ancestors.push(place);
+        id = place.1;
+    }
+    ancestors
+}
+
+fn api_error(status: StatusCode, code: i32, message: &str) -> Response {
+    let error = ApiError {
+        code,
+        message: message.to_string(),
+        details: vec![],
+    };
+    (status, Json(error)).into_response()
+}
+
+/// Builds `count` itineraries for the query, alternating carriers, departure times and
+/// direct or one-stop (via `ICN`) legs, priced from 2,500 upwards in milli-units.
+pub fn synthesize(query: &Query, count: usize) -> FightResult {
+    let mut results = FightResult::default();
+
+    for (id, name, iata) in CARRIERS {
+        results.carriers.insert(
+            id.to_string(),
+            Carrier {
+                name: name.to_string(),
+                alliance_id: "".to_string(),
+                image_url: "".to_string(),
+                iata: iata.to_string(),
+            },
+        );
+    }
+    results.agents.insert(
+        "mock".to_string(),
+        Agent {
+            name: "Mock Travel".to_string(),
+            kind: AgentType::AgentTypeTravelAgent,
+            image_url: "".to_string(),
+            feedback_count: 0,
+            rating: 0.0,
+            rating_breakdown: None,
+            is_optimised_for_mobile: true,
+        },
+    );
+
+    let mut place = |iata: &str| -> String {
+        let id = format!("mock-{iata}");
+        results
+            .places
+            .entry(id.clone())
+            .or_insert_with(|| ResponsePlace {
+                entity_id: id.clone(),
+                parent_id: "".to_string(),
+                name: iata.to_string(),
+                iata: iata.to_string(),
+                kind: PlaceType::PlaceTypeAirport,
+                coordinates: None,
+            });
+        id
+    };
+    let hub = place(HUB_IATA);
+    let places = query
+        .query_legs
+        .iter()
+        .map(|leg| {
+            (
+                place(airport(&leg.origin_place_id).unwrap_or_default()),
+                place(airport(&leg.destination_place_id).unwrap_or_default()),
+                leg.date.to_naive_date().unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for i in 0..count {
+        let (carrier_id, _, _) = CARRIERS[i % CARRIERS.len()];
+        let stop_count = (i % 3 == 2) as u16;
+
+        let leg_ids = places
+            .iter()
+            .enumerate()
+            .map(|(j, (origin, destination, date))| {
+                let departure = date
+                    .and_hms_opt(6 + (i as u32 * 2) % 16, (i as u32 * 25) % 60, 0)
+                    .unwrap_or_default();
+                let flight_minutes = 120 + 30 * (i as i64 % 3);
+
+                let hops = if stop_count == 0 {
+                    vec![(origin.clone(), destination.clone())]
+                } else {
+                    vec![
+                        (origin.clone(), hub.clone()),
+                        (hub.clone(), destination.clone()),
+                    ]
+                };
+                let mut segment_ids = vec![];
+                let mut at = departure;
+                for (k, (from, to)) in hops.into_iter().enumerate() {
+                    let arrival = at + chrono::Duration::minutes(flight_minutes);
+                    let segment_id = format!("{from}-{to}-{i}-{j}-{k}");
+                    results.segments.insert(
+                        segment_id.clone(),
+                        Segment {
+                            origin_place_id: from,
+                            destination_place_id: to,
+                            departure_date_time: date_time(at),
+                            arrival_date_time: date_time(arrival),
+                            duration_in_minutes: flight_minutes as u32,
+                            marking_flight_number: Some(format!("{}", 100 + i)),
+                            marking_carrier_id: Some(carrier_id.to_string()),
+                            operating_carrier_id: Some(carrier_id.to_string()),
+                        },
+                    );
+                    segment_ids.push(segment_id);
+                    // One hour connection at the hub.
+                    at = arrival + chrono::Duration::minutes(60);
+                }
+                let arrival = at - chrono::Duration::minutes(60);
+
+                let leg_id = format!("{origin}-{destination}-{i}-{j}");
+                results.legs.insert(
+                    leg_id.clone(),
+                    Leg {
+                        origin_place_id: origin.clone(),
+                        destination_place_id: destination.clone(),
+                        departure_date_time: date_time(departure),
+                        arrival_date_time: date_time(arrival),
+                        duration_in_minutes: (arrival - departure).num_minutes() as u32,
+                        stop_count,
+                        marketing_carrier_ids: vec![carrier_id.to_string()],
+                        operating_carrier_ids: vec![carrier_id.to_string()],
+                        segment_ids,
+                    },
+                );
+                leg_id
+            })
+            .collect::<Vec<_>>();
+
+        let amount = (2500 + 350 * i as u64) * 1000;
+        let price = Price {
+            amount: amount.to_string(),
+            unit: "PRICE_UNIT_MILLI".to_string(),
+            update_status: "PRICE_STATUS_UNSPECIFIED".to_string(),
+        };
+        let itinerary_id = leg_ids.join("|");
+        results.itineraries.insert(
+            itinerary_id.clone(),
+            Itinerary {
+                pricing_options: vec![PriceOption {
+                    id: format!("price-{i}"),
+                    price: price.clone(),
+                    agent_ids: vec!["mock".to_string()],
+                    items: vec![PricingItem {
+                        price: Some(price),
+                        agent_id: "mock".to_string(),
+                        deep_link: format!("{MOCK_BOOKING_URL}?itinerary={itinerary_id}"),
+                        fares: vec![],
+                        booking_proposition: "PBOOK".to_string(),
+                    }],
+                    transfer_type: "TRANSFER_TYPE_MANAGED".to_string(),
+                }],
+                leg_ids,
+                sustainability_data: None,
+            },
+        );
+    }
+
+    results
+}
+
+/// One quote per day with a pseudo-random price between 1,800 and 3,000, return quotes
+/// come back three days later.
+pub fn quote(
+    origin: &str,
+    destination: &str,
+    round_trip: bool,
+    days: &[NaiveDate],
+) -> IndicativeResults {
+    let mut results = IndicativeResults::default();
+    for iata in [origin, destination] {
+        let id = format!("mock-{iata}");
+        results.places.insert(
+            id.clone(),
+            ResponsePlace {
+                entity_id: id,
+                parent_id: "".to_string(),
+                name: iata.to_string(),
+                iata: iata.to_string(),
+                kind: PlaceType::PlaceTypeAirport,
+                coordinates: None,
+            },
+        );
+    }
+
+    for day in days {
+        let (carrier_id, name, iata) = CARRIERS[day.ordinal() as usize % CARRIERS.len()];
+        results
+            .carriers
+            .entry(carrier_id.to_string())
+            .or_insert(Carrier {
+                name: name.to_string(),
+                alliance_id: "".to_string(),
+                image_url: "".to_string(),
+                iata: iata.to_string(),
+            });
+
+        let leg = |from: &str, to: &str, date: NaiveDate| QuoteLeg {
+            origin_place_id: format!("mock-{from}"),
+            destination_place_id: format!("mock-{to}"),
+            departure_date_time: date_time(date.and_hms_opt(0, 0, 0).unwrap_or_default()),
+            marketing_carrier_id: carrier_id.to_string(),
+        };
+        let amount = (1800 + (day.ordinal() as u64 * 373) % 1200) * 1000;
+        results.quotes.insert(
+            format!("{origin}-{destination}-{day}"),
+            Quote {
+                min_price: Price {
+                    amount: amount.to_string(),
+                    unit: "PRICE_UNIT_MILLI".to_string(),
+                    update_status: "".to_string(),
+                },
+                is_direct: day.ordinal() % 3 != 0,
+                outbound_leg: leg(origin, destination, *day),
+                inbound_leg: round_trip
+                    .then(|| leg(destination, origin, *day + chrono::Duration::days(3))),
+            },
+        );
+    }
+
+    results
+}
+
+fn today() -> NaiveDate {
+    let days = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|e| e.as_secs() / 86_400)
+        .unwrap_or_default();
+    NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default() + chrono::Duration::days(days as i64)
+}
+
+fn date_time(value: NaiveDateTime) -> ResponseDateTime {
+    ResponseDateTime {
+        year: value.year() as u16,
+        month: value.month() as u8,
+        day: value.day() as u8,
+        hour: value.hour() as u8,
+        minute: value.minute() as u8,
+        second: value.second() as u8,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use secrecy::Secret;
+
+    use super::*;
+    use crate::api::SkyscannerApi;
+    use crate::configuration::{
+        CacheSettings, CultureSettings, HttpSettings, RateLimitSettings, RetrySettings,
+        SearchSettings, Settings,
+    };
+    use crate::datasource::Datasource;
+    use crate::domain::{Currency, Date, IndicativeQuery, IndicativeQueryLeg, Place, QueryLeg};
+    use crate::error::SkyscannerError;
+    use crate::resolver::{PlaceResolver, Resolution};
+    use crate::services::Services;
+
+    fn spawn(settings: MockSettings) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(serve(listener, settings));
+        format!("http://127.0.0.1:{port}/apiservices/v3/")
+    }
+
+    fn settings(base_url: String, api_key: &str) -> Settings {
+        Settings {
+            api_key: Secret::new(api_key.to_string()),
+            search: SearchSettings {
+                poll_interval_milliseconds: 0,
+                deadline_seconds: 10,
+                max_polls: 10,
+                max_concurrent_searches: 2,
+                max_trips: 200,
+            },
+            retry: RetrySettings {
+                max_attempts: 1,
+                base_delay_milliseconds: 0,
+                max_delay_milliseconds: 0,
+                jitter_milliseconds: 0,
+            },
+            rate_limit: RateLimitSettings {
+                requests_per_second: 0.0,
+                burst: 1,
+                max_in_flight: 4,
+            },
+            http: HttpSettings {
+                base_url,
+                timeout_milliseconds: 5000,
+                connect_timeout_milliseconds: 1000,
+                proxy: None,
+                user_agent: "skyscanner-test".to_string(),
+            },
+            cache: CacheSettings {
+                directory: std::env::temp_dir().display().to_string(),
+                ttl_seconds: 0,
+            },
+            culture: CultureSettings::default(),
+            cassette: None,
+        }
+    }
+
+    fn query(from: &str, to: &str) -> Query {
+        let from = Place::new(Some(from.to_string()), None);
+        let to = Place::new(Some(to.to_string()), None);
+        Query::default()
+            .set_market("TW".to_string())
+            .set_locale("zh-TW".to_string())
+            .set_currency("TWD".to_string())
+            .set_query_leg(QueryLeg::new(
+                from.clone(),
+                to.clone(),
+                Date::new(2024, 6, 1),
+            ))
+            .set_query_leg(QueryLeg::new(to, from, Date::new(2024, 6, 5)))
+    }
+
+    #[tokio::test]
+    async fn it_works_when_searching_against_the_mock_server() {
+        let base_url = spawn(MockSettings {
+            itineraries: 6,
+            ..MockSettings::default()
+        });
+        let config = settings(base_url, "key");
+        let services = Services::new(&config).unwrap();
+
+        let carriers = services.get_carriers().await.unwrap();
+        assert_eq!(carriers.find_id("CX"), Some("-32222"));
+
+        let explore =
+            IndicativeQuery::new("TW".to_string(), "zh-TW".to_string(), "TWD".to_string())
+                .set_query_leg(IndicativeQueryLeg::fixed_date(
+                    Place::new(Some("TPE".to_string()), None),
+                    Place::anywhere(),
+                    Date::new(2024, 6, 1),
+                ));
+        let res = services
+            .search_indicative_prices(&IndicativeSearchRequest::new(explore))
+            .await
+            .unwrap();
+        let destinations = res
+            .content
+            .results
+            .cheapest_destinations(&Currency::plain("TWD"));
+        assert_eq!(destinations.len(), EXPLORE_IATAS.len());
+
+        let mut datasource = Datasource::new(query("TPE", "HKG"), services, config.search.clone());
+        while let Some(res) = datasource.next().await {
+            res.unwrap();
+        }
+
+        let snapshot = datasource.snapshot();
+        assert_eq!(snapshot.itineraries.len(), 6);
+        assert!(snapshot
+            .itineraries
+            .values()
+            .all(|e| e.leg_ids.iter().all(|id| snapshot.legs.contains_key(id))));
+
+        let itinerary_id = snapshot.itineraries.keys().next().unwrap().clone();
+        let refreshed = datasource.refresh(&itinerary_id).await.unwrap();
+        assert!(!refreshed.price_changed());
+        assert!(!refreshed.pricing_options.is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_works_when_searching_places_by_name_against_the_mock_server() {
+        let config = settings(spawn(MockSettings::default()), "key");
+        let services = Services::new(&config).unwrap();
+        let resolver = |services: Services| {
+            PlaceResolver::new(services, "TW".to_string(), "zh-TW".to_string())
+        };
+
+        let taipei = Place::new(None, Some("27547373".to_string()));
+        let res = resolver(services.clone()).resolve("Taipei").await.unwrap();
+        assert_eq!(res, Resolution::Resolved(taipei.clone()));
+        let res = resolver(services.clone()).resolve("Japan").await.unwrap();
+        assert_eq!(res, Resolution::NotFound);
+        let japan = Place::new(None, Some("29475330".to_string()));
+        let res = resolver(services.clone())
+            .with_countries()
+            .resolve("Japan")
+            .await
+            .unwrap();
+        assert_eq!(res, Resolution::Resolved(japan.clone()));
+
+        let locations = services.get_flight_locations("zh-TW").await.unwrap();
+        assert_eq!(locations.places.len(), PLACES.len());
+
+        let explore =
+            IndicativeQuery::new("TW".to_string(), "zh-TW".to_string(), "TWD".to_string())
+                .set_query_leg(IndicativeQueryLeg::fixed_date(
+                    taipei.clone(),
+                    japan,
+                    Date::new(2024, 6, 1),
+                ));
+        let res = services
+            .search_indicative_prices(&IndicativeSearchRequest::new(explore))
+            .await
+            .unwrap();
+        let mut destinations = res
+            .content
+            .results
+            .cheapest_destinations(&Currency::plain("TWD"))
+            .into_iter()
+            .map(|e| e.iata)
+            .collect::<Vec<_>>();
+        destinations.sort();
+        assert_eq!(destinations, vec!["HND", "KIX", "NRT"]);
+
+        let hong_kong = Place::new(None, Some("27546079".to_string()));
+        let query = Query::default()
+            .set_market("TW".to_string())
+            .set_locale("zh-TW".to_string())
+            .set_currency("TWD".to_string())
+            .set_query_leg(QueryLeg::new(taipei, hong_kong, Date::new(2024, 6, 1)));
+        let searched = Datasource::new(query, services, config.search)
+            .search()
+            .await;
+        assert!(searched.incomplete.is_none());
+        for iata in ["TPE", "HKG"] {
+            assert!(searched.results.places.values().any(|e| e.iata == iata));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_works_when_the_mock_server_reports_errors() {
+        let base_url = spawn(MockSettings {
+            api_key: Some("key".to_string()),
+            ..MockSettings::default()
+        });
+
+        let services = Services::new(&settings(base_url.clone(), "wrong")).unwrap();
+        let error = services.get_locales().await.unwrap_err();
+        assert!(matches!(error, SkyscannerError::Unauthorized(_)));
+
+        let config = settings(base_url, "key");
+        let services = Services::new(&config).unwrap();
+        let error = services
+            .create_a_request_to_find_flights(&CreateFlightsRequest::new(
+                query("TPE", "HKG").set_market("XX".to_string()),
+            ))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, SkyscannerError::Validation { .. }));
+
+        let datasource = Datasource::new(query("TPE", FAILING_IATA), services, config.search);
+        let error = datasource.search().await.incomplete.unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SkyscannerError>(),
+            Some(SkyscannerError::SearchFailed { .. })
+        ));
+    }
+}
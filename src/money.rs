@@ -0,0 +1,171 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use crate::domain::Currency;
+
+/// An amount of money in thousandths of the currency's major unit, e.g. 2,500 TWD is
+/// `2_500_000` milli-units.
+///
+/// Amounts of different currencies are never added, subtracted or ordered.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Money {
+    milli: i64,
+    currency: String,
+}
+
+impl Money {
+    pub fn from_milli(milli: i64, currency: impl Into<String>) -> Self {
+        Self {
+            milli,
+            currency: currency.into(),
+        }
+    }
+
+    pub fn milli(&self) -> i64 {
+        self.milli
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        self.same_currency(other)?;
+        Some(Self::from_milli(
+            self.milli.checked_add(other.milli)?,
+            self.currency.clone(),
+        ))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Option<Money> {
+        self.same_currency(other)?;
+        Some(Self::from_milli(
+            self.milli.checked_sub(other.milli)?,
+            self.currency.clone(),
+        ))
+    }
+
+    pub fn checked_mul(&self, factor: i64) -> Option<Money> {
+        Some(Self::from_milli(
+            self.milli.checked_mul(factor)?,
+            self.currency.clone(),
+        ))
+    }
+
+    /// Writes the amount the way `style` describes its currency, e.g. `NT$2,500` or
+    /// `1.234,50 €`, rounded half away from zero to the currency's decimal digits.
+    pub fn format(&self, style: &Currency) -> String {
+        let digits = style.decimal_digits.min(3);
+        let scale = 10_u64.pow(3 - digits);
+        let rounded = (self.milli.unsigned_abs() + scale / 2) / scale;
+        let precision = 10_u64.pow(digits);
+        let (major, minor) = (rounded / precision, rounded % precision);
+
+        let mut amount = group(major, &style.thousands_separator);
+        if digits > 0 {
+            amount.push_str(&style.decimal_separator);
+            amount.push_str(&format!("{minor:0width$}", width = digits as usize));
+        }
+
+        let space = if style.space_between_amount_and_symbol {
+            " "
+        } else {
+            ""
+        };
+        let sign = if self.milli < 0 { "-" } else { "" };
+        if style.symbol_on_left {
+            format!("{sign}{}{space}{amount}", style.symbol)
+        } else {
+            format!("{sign}{amount}{space}{}", style.symbol)
+        }
+    }
+
+    fn same_currency(&self, other: &Money) -> Option<()> {
+        (self.currency == other.currency).then_some(())
+    }
+}
+
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.same_currency(other)?;
+        Some(self.milli.cmp(&other.milli))
+    }
+}
+
+/// The amount without trailing zeros, followed by the currency code, e.g. `2500.5 TWD`.
+impl Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", decimal(self.milli), self.currency)
+    }
+}
+
+/// Writes milli-units as a plain decimal number, e.g. `2500500` as `2500.5`.
+pub fn decimal(milli: i64) -> String {
+    let sign = if milli < 0 { "-" } else { "" };
+    let (major, minor) = (milli.unsigned_abs() / 1000, milli.unsigned_abs() % 1000);
+    if minor == 0 {
+        format!("{sign}{major}")
+    } else {
+        let minor = format!("{minor:03}");
+        format!("{sign}{major}.{}", minor.trim_end_matches('0'))
+    }
+}
+
+fn group(value: u64, separator: &str) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn style(symbol: &str, on_left: bool, digits: u32) -> Currency {
+        Currency {
+            code: "XXX".to_string(),
+            symbol: symbol.to_string(),
+            thousands_separator: if on_left { "," } else { "." }.to_string(),
+            decimal_separator: if on_left { "." } else { "," }.to_string(),
+            symbol_on_left: on_left,
+            space_between_amount_and_symbol: !on_left,
+            decimal_digits: digits,
+        }
+    }
+
+    #[test]
+    fn it_works_when_doing_arithmetic() {
+        let a = Money::from_milli(2_500_100, "TWD");
+        let b = Money::from_milli(1_000_200, "TWD");
+
+        assert_eq!(a.checked_add(&b), Some(Money::from_milli(3_500_300, "TWD")));
+        assert_eq!(a.checked_sub(&b), Some(Money::from_milli(1_499_900, "TWD")));
+        assert_eq!(b.checked_mul(3), Some(Money::from_milli(3_000_600, "TWD")));
+        assert!(a > b);
+
+        let c = Money::from_milli(1, "HKD");
+        assert_eq!(a.checked_add(&c), None);
+        assert_eq!(a.partial_cmp(&c), None);
+        assert_eq!(Money::from_milli(i64::MAX, "TWD").checked_add(&a), None);
+    }
+
+    #[test]
+    fn it_works_when_formatting() {
+        let money = Money::from_milli(1_234_567_500, "TWD");
+        assert_eq!(money.to_string(), "1234567.5 TWD");
+        assert_eq!(money.format(&style("NT$", true, 0)), "NT$1,234,568");
+        assert_eq!(money.format(&style("€", false, 2)), "1.234.567,50 €");
+        assert_eq!(
+            Money::from_milli(-2_005, "USD").format(&style("$", true, 2)),
+            "-$2.01"
+        );
+        assert_eq!(decimal(2_500_000), "2500");
+        assert_eq!(decimal(-50), "-0.05");
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+
+use crate::configuration::RateLimitSettings;
+
+/// A token bucket combined with a cap on in-flight requests.
+///
+/// Clones share the same bucket and permits, so every clone of `Services` draws from one quota.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    in_flight: Arc<Semaphore>,
+    requests_per_second: f64,
+    capacity: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        let capacity = f64::from(settings.burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                updated_at: Instant::now(),
+            })),
+            in_flight: Arc::new(Semaphore::new(settings.max_in_flight.max(1))),
+            requests_per_second: settings.requests_per_second,
+            capacity,
+        }
+    }
+
+    /// Waits for an in-flight slot and a token, the slot is released when the permit drops.
+    ///
+    /// A non-positive `requests_per_second` disables the token bucket.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        let permit = self
+            .in_flight
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The rate limiter semaphore is never closed");
+
+        if self.requests_per_second <= 0.0 {
+            return permit;
+        }
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+                let now = Instant::now();
+                let refill =
+                    now.duration_since(bucket.updated_at).as_secs_f64() * self.requests_per_second;
+                bucket.tokens = (bucket.tokens + refill).min(self.capacity);
+                bucket.updated_at = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return permit;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.requests_per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter(requests_per_second: f64, burst: u32, max_in_flight: usize) -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            requests_per_second,
+            burst,
+            max_in_flight,
+        })
+    }
+
+    #[tokio::test]
+    async fn it_works_when_throttling_beyond_the_burst() {
+        let limiter = limiter(20.0, 2, 10);
+        let started_at = Instant::now();
+        for _ in 0..4 {
+            drop(limiter.acquire().await);
+        }
+        // Two tokens are free, the next two need 50ms each.
+        assert!(started_at.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn it_works_when_sharing_in_flight_slots_between_clones() {
+        let limiter = limiter(0.0, 1, 1);
+        let cloned = limiter.clone();
+
+        let permit = limiter.acquire().await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), cloned.acquire()).await;
+        assert!(waiting.is_err());
+
+        drop(permit);
+        let waiting = tokio::time::timeout(Duration::from_millis(20), cloned.acquire()).await;
+        assert!(waiting.is_ok());
+    }
+}
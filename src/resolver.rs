@@ -0,0 +1,242 @@
+use std::fmt::{Display, Formatter};
+
+use crate::api::SkyscannerApi;
+use crate::domain::{AutosuggestRequest, Place, PlaceType};
+use crate::error::SkyscannerError;
+
+/// Turns what a user types, e.g. `TPE`, `Taipei` or `Hong Kong`, into a query `Place`.
+pub struct PlaceResolver<A: SkyscannerApi> {
+    api: A,
+    market: String,
+    locale: String,
+    countries: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    Resolved(Place),
+    /// Several places match equally well, the caller has to pick one.
+    Ambiguous(Vec<Candidate>),
+    NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub entity_id: String,
+    pub iata: String,
+    pub name: String,
+    pub hierarchy: String,
+    pub kind: PlaceType,
+}
+
+impl<A: SkyscannerApi> PlaceResolver<A> {
+    pub fn new(api: A, market: String, locale: String) -> Self {
+        Self {
+            api,
+            market,
+            locale,
+            countries: false,
+        }
+    }
+
+    /// Resolves countries too, e.g. `Japan`, only indicative searches accept them.
+    pub fn with_countries(mut self) -> Self {
+        self.countries = true;
+        self
+    }
+
+    /// Upper-case three letter input is taken as an IATA code as is. Anything else goes
+    /// through autosuggest, falling back to the geo hierarchy when autosuggest finds nothing.
+    ///
+    /// A single candidate, or a single city named exactly like the input, is resolved.
+    pub async fn resolve(&self, input: &str) -> Result<Resolution, SkyscannerError> {
+        let input = input.trim();
+        if input.len() == 3 && input.chars().all(|c| c.is_ascii_uppercase()) {
+            return Ok(Resolution::Resolved(Place::new(
+                Some(input.to_string()),
+                None,
+            )));
+        }
+
+        let mut candidates = self.suggest(input).await?;
+        if candidates.is_empty() {
+            candidates = self.locate(input).await?;
+        }
+
+        let exact_cities = candidates
+            .iter()
+            .filter(|c| c.kind == PlaceType::PlaceTypeCity && c.name.eq_ignore_ascii_case(input))
+            .collect::<Vec<_>>();
+
+        Ok(match (candidates.len(), exact_cities.as_slice()) {
+            (0, _) => Resolution::NotFound,
+            (1, _) => Resolution::Resolved(candidates[0].to_place()),
+            (_, [city]) => Resolution::Resolved(city.to_place()),
+            _ => Resolution::Ambiguous(candidates),
+        })
+    }
+
+    async fn suggest(&self, input: &str) -> Result<Vec<Candidate>, SkyscannerError> {
+        let mut req =
+            AutosuggestRequest::new(self.market.clone(), self.locale.clone(), input.to_string());
+        if self.countries {
+            req = req.include_countries();
+        }
+        let res = self.api.autosuggest_flights(&req).await?;
+        Ok(res
+            .places
+            .into_iter()
+            .map(|place| Candidate {
+                entity_id: place.entity_id,
+                iata: place.iata_code,
+                name: place.name,
+                hierarchy: place.hierarchy,
+                kind: place.kind,
+            })
+            .collect())
+    }
+
+    async fn locate(&self, input: &str) -> Result<Vec<Candidate>, SkyscannerError> {
+        let needle = input.to_lowercase();
+        let res = self.api.get_flight_locations(&self.locale).await?;
+        let mut candidates = res
+            .places
+            .into_values()
+            .filter(|place| match place.kind {
+                PlaceType::PlaceTypeCity | PlaceType::PlaceTypeAirport => true,
+                PlaceType::PlaceTypeCountry => self.countries,
+                _ => false,
+            })
+            .filter(|place| place.name.to_lowercase().contains(&needle))
+            .map(|place| Candidate {
+                entity_id: place.entity_id,
+                iata: place.iata,
+                name: place.name,
+                hierarchy: "".to_string(),
+                kind: place.kind,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.name.cmp(&b.name).then(a.iata.cmp(&b.iata)));
+        Ok(candidates)
+    }
+}
+
+impl Candidate {
+    /// Airports are queried by their IATA code, cities by entity id so every airport of the
+    /// city is included.
+    pub fn to_place(&self) -> Place {
+        if self.kind == PlaceType::PlaceTypeAirport && !self.iata.is_empty() {
+            Place::new(Some(self.iata.clone()), None)
+        } else {
+            Place::new(None, Some(self.entity_id.clone()))
+        }
+    }
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            PlaceType::PlaceTypeAirport => "airport",
+            PlaceType::PlaceTypeCity => "city",
+            PlaceType::PlaceTypeCountry => "country",
+            PlaceType::PlaceTypeContinent => "continent",
+            PlaceType::PlaceTypeUnspecified => "place",
+        };
+        write!(f, "{}", self.name)?;
+        if !self.iata.is_empty() {
+            write!(f, " ({})", self.iata)?;
+        }
+        if !self.hierarchy.is_empty() {
+            write!(f, ", {}", self.hierarchy)?;
+        }
+        write!(f, " [{kind}]")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::{AutosuggestResponse, GeoResponse};
+    use crate::fake::FakeApi;
+
+    fn suggestions(places: serde_json::Value) -> AutosuggestResponse {
+        serde_json::from_value(serde_json::json!({ "places": places })).unwrap()
+    }
+
+    fn resolver(api: FakeApi) -> PlaceResolver<FakeApi> {
+        PlaceResolver::new(api, "TW".to_string(), "en-GB".to_string())
+    }
+
+    #[tokio::test]
+    async fn it_works_when_resolving_an_iata_code() {
+        let res = resolver(FakeApi::new()).resolve("TPE").await.unwrap();
+        assert_eq!(
+            res,
+            Resolution::Resolved(Place::new(Some("TPE".to_string()), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_works_when_resolving_a_city_name() {
+        let api = FakeApi::new().set_suggestions(suggestions(serde_json::json!([
+            { "entityId": "27547373", "iataCode": "TPE", "name": "Taipei", "type": "PLACE_TYPE_CITY" },
+            { "entityId": "95673372", "iataCode": "TPE", "name": "Taipei Taoyuan", "type": "PLACE_TYPE_AIRPORT" },
+            { "entityId": "128667080", "iataCode": "TSA", "name": "Taipei Songshan", "type": "PLACE_TYPE_AIRPORT" },
+        ])));
+
+        let res = resolver(api).resolve("taipei").await.unwrap();
+        assert_eq!(
+            res,
+            Resolution::Resolved(Place::new(None, Some("27547373".to_string())))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_works_when_the_input_is_ambiguous() {
+        let api = FakeApi::new().set_suggestions(suggestions(serde_json::json!([
+            { "entityId": "1", "iataCode": "SJC", "name": "San Jose", "hierarchy": "United States", "type": "PLACE_TYPE_AIRPORT" },
+            { "entityId": "2", "iataCode": "SJO", "name": "San Jose", "hierarchy": "Costa Rica", "type": "PLACE_TYPE_AIRPORT" },
+        ])));
+
+        let res = resolver(api).resolve("San Jose").await.unwrap();
+        let Resolution::Ambiguous(candidates) = res else {
+            panic!("Expected an ambiguous resolution");
+        };
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(
+            candidates[1].to_place(),
+            Place::new(Some("SJO".to_string()), None)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_works_when_falling_back_to_the_geo_hierarchy() {
+        let locations = serde_json::from_value::<GeoResponse>(serde_json::json!({
+            "places": {
+                "95673827": { "entityId": "95673827", "name": "Hong Kong International", "iata": "HKG", "type": "PLACE_TYPE_AIRPORT" },
+                "29475330": { "entityId": "29475330", "name": "Japan", "type": "PLACE_TYPE_COUNTRY" },
+            }
+        }))
+        .unwrap();
+        let api = FakeApi::new().set_locations(locations);
+
+        let res = resolver(api.clone()).resolve("Hong Kong").await.unwrap();
+        assert_eq!(
+            res,
+            Resolution::Resolved(Place::new(Some("HKG".to_string()), None))
+        );
+
+        let res = resolver(api.clone()).resolve("Japan").await.unwrap();
+        assert_eq!(res, Resolution::NotFound);
+
+        let res = resolver(api)
+            .with_countries()
+            .resolve("Japan")
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            Resolution::Resolved(Place::new(None, Some("29475330".to_string())))
+        );
+    }
+}
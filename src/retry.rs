@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::configuration::RetrySettings;
+
+/// Exponential backoff with additive jitter, used by `Services` around every request.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(settings: &RetrySettings) -> Self {
+        Self {
+            max_attempts: settings.max_attempts.max(1),
+            base_delay: Duration::from_millis(settings.base_delay_milliseconds),
+            max_delay: Duration::from_millis(settings.max_delay_milliseconds),
+            jitter: Duration::from_millis(settings.jitter_milliseconds),
+        }
+    }
+
+    /// Whether another attempt may follow the given (1-based) attempt.
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    /// The delay to wait after the given (1-based) failed attempt.
+    ///
+    /// A `Retry-After` sent by the server wins over the computed backoff, up to the maximum
+    /// delay.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let jitter = if self.jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            let millis = self.jitter.as_millis() as u64;
+            Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+        };
+        self.backoff(attempt) + jitter
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+/// Whether a request can be sent again after it may have reached the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Idempotency {
+    Idempotent,
+    /// The request creates something, e.g. a search session, so it's only sent again when the
+    /// server surely didn't act on it.
+    NotIdempotent,
+}
+
+/// Quota errors are worth another attempt, and server side failures unless the request isn't
+/// idempotent; anything else is not.
+pub fn is_retryable_status(status: StatusCode, idempotency: Idempotency) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status.is_server_error() && idempotency == Idempotency::Idempotent)
+}
+
+/// Connection errors are worth another attempt, and timeouts unless the request isn't
+/// idempotent.
+pub fn is_retryable_error(error: &reqwest::Error, idempotency: Idempotency) -> bool {
+    error.is_connect() || (error.is_timeout() && idempotency == Idempotency::Idempotent)
+}
+
+/// Parses a `Retry-After` header given in seconds, HTTP dates are ignored.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy(jitter_milliseconds: u64) -> RetryPolicy {
+        RetryPolicy::new(&RetrySettings {
+            max_attempts: 4,
+            base_delay_milliseconds: 100,
+            max_delay_milliseconds: 500,
+            jitter_milliseconds,
+        })
+    }
+
+    #[test]
+    fn it_works_when_computing_exponential_backoff() {
+        let policy = policy(0);
+        let delays = (1..=5)
+            .map(|attempt| policy.delay(attempt, None).as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+        assert!(policy.should_retry(3));
+        assert!(!policy.should_retry(4));
+    }
+
+    #[test]
+    fn it_works_when_adding_jitter() {
+        let policy = policy(50);
+        for _ in 0..100 {
+            let delay = policy.delay(2, None).as_millis();
+            assert!((200..=250).contains(&delay));
+        }
+    }
+
+    #[test]
+    fn it_works_when_honoring_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        let server_delay = retry_after(&headers);
+        assert_eq!(server_delay, Some(Duration::from_secs(7)));
+        assert_eq!(
+            policy(50).delay(1, server_delay),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            policy(50).delay(1, Some(Duration::from_millis(300))),
+            Duration::from_millis(300)
+        );
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn it_works_when_checking_retryable_status() {
+        use Idempotency::*;
+
+        assert!(is_retryable_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            Idempotent
+        ));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY, Idempotent));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED, Idempotent));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST, Idempotent));
+
+        assert!(is_retryable_status(
+            StatusCode::TOO_MANY_REQUESTS,
+            NotIdempotent
+        ));
+        assert!(!is_retryable_status(StatusCode::BAD_GATEWAY, NotIdempotent));
+    }
+}
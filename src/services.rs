@@ -1,34 +1,70 @@
-use anyhow::Context;
-use reqwest::{Error, Url};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode, Url};
 use secrecy::ExposeSecret;
 
+use crate::api::SkyscannerApi;
+use crate::cassette::{Interaction, Tape};
 use crate::configuration::Settings;
-use crate::domain::{CreateFlightsRequest, FlightsResponse, Locales, Markets};
+use crate::domain::{
+    AutosuggestRequest, AutosuggestResponse, Carriers, CreateFlightsRequest, Currencies,
+    FlightsResponse, GeoResponse, IndicativeResponse, IndicativeSearchRequest,
+    ItineraryRefreshRequest, ItineraryRefreshResponse, Locales, Markets, NearestCulture,
+    ResponseStatus,
+};
+use crate::error::SkyscannerError;
+use crate::rate_limit::RateLimiter;
+use crate::retry::{
+    is_retryable_error, is_retryable_status, retry_after, Idempotency, RetryPolicy,
+};
 
 #[derive(Clone)]
 pub struct Services {
     client: reqwest::Client,
+    // Sent with every request rather than as default headers of the client, so a recorded
+    // request has the headers it was actually sent with.
+    headers: reqwest::header::HeaderMap,
     base_url: Url,
+    retry: RetryPolicy,
+    rate_limiter: RateLimiter,
+    tape: Option<Tape>,
 }
 
 impl Services {
-    pub fn new(config: &Settings) -> Result<Self, anyhow::Error> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            "x-api-key",
+    pub fn new(config: &Settings) -> Result<Self, SkyscannerError> {
+        let mut api_key =
             reqwest::header::HeaderValue::from_str(config.api_key.expose_secret().as_str())
-                .unwrap(),
-        );
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .context("Can't build a request client")?;
+                .map_err(|_| {
+                    SkyscannerError::Configuration("The API key is not a valid header".to_string())
+                })?;
+        api_key.set_sensitive(true);
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-api-key", api_key);
+        let mut builder = reqwest::Client::builder()
+            .timeout(config.http.timeout())
+            .connect_timeout(config.http.connect_timeout())
+            .user_agent(config.http.user_agent.as_str());
+        if let Some(proxy) = &config.http.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+        }
+        let client = builder.build()?;
 
-        let url = Url::parse("https://partners.api.skyscanner.net/apiservices/v3/")?;
+        // Without the trailing slash `Url::join` would drop the last path segment.
+        let mut base_url = config.http.base_url.clone();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        let url = Url::parse(&base_url).map_err(|e| {
+            SkyscannerError::Configuration(format!("Invalid base url {base_url}: {e}"))
+        })?;
 
         Ok(Self {
             client,
+            headers,
             base_url: url,
+            retry: RetryPolicy::new(&config.retry),
+            rate_limiter: RateLimiter::new(&config.rate_limit),
+            tape: config.cassette.as_ref().map(Tape::new).transpose()?,
         })
     }
 
@@ -36,59 +72,269 @@ impl Services {
         self.base_url.join(rest).expect("Can't join the url")
     }
 
-    pub async fn get_markets(&self, locale: &str) -> Result<Markets, Error> {
-        let uri = format!("culture/markets/{locale}");
-        let url = self.get_url(uri.as_str());
+    /// Sends a request to `uri` and returns the body of a successful response.
+    ///
+    /// Every attempt waits for the shared rate limiter first. Timeouts, connection errors,
+    /// `429` and `5xx` responses are retried according to the retry policy, only connection
+    /// errors and `429` when the request isn't idempotent; once the attempts run out the last
+    /// failure is turned into a `SkyscannerError`.
+    ///
+    /// When recording, the final response is written to the cassette; when replaying, the
+    /// response comes from the cassette and nothing is sent.
+    async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        idempotency: Idempotency,
+    ) -> Result<String, SkyscannerError> {
+        if let Some(Tape::Replay(player)) = &self.tape {
+            let interaction = player.replay(method.as_str(), uri, body.as_ref())?;
+            let status = StatusCode::from_u16(interaction.status)
+                .map_err(|e| SkyscannerError::Cassette(e.to_string()))?;
+            return into_body(status, None, interaction.response_body);
+        }
 
-        let res = self.client.get(url.as_str()).send().await;
+        let url = self.get_url(uri);
+        let request = || {
+            let request = self
+                .client
+                .request(method.clone(), url.as_str())
+                .headers(self.headers.clone());
+            match &body {
+                Some(body) => request.json(body),
+                None => request,
+            }
+        };
+
+        let mut attempt = 1;
+        let mut request_headers = Default::default();
+        // The permit is held until the body is read, so it covers the whole exchange.
+        let (res, permit) = loop {
+            let permit = self.rate_limiter.acquire().await;
+            let res = match request().build() {
+                Ok(request) => {
+                    request_headers = Interaction::request_headers(request.headers());
+                    self.client.execute(request).await
+                }
+                Err(e) => Err(e),
+            };
+            let retry_after = match &res {
+                Ok(res) if is_retryable_status(res.status(), idempotency) => {
+                    retry_after(res.headers())
+                }
+                Err(e) if is_retryable_error(e, idempotency) => None,
+                _ => break (res?, permit),
+            };
+
+            if !self.retry.should_retry(attempt) {
+                break (res?, permit);
+            }
+            drop(permit);
+            tokio::time::sleep(self.retry.delay(attempt, retry_after)).await;
+            attempt += 1;
+        };
+
+        let status = res.status();
+        let retry_after = retry_after(res.headers());
+        let response_body = res.text().await?;
+        drop(permit);
 
-        match res {
-            Ok(res) => res.json::<Markets>().await,
-            Err(e) => Err(e),
+        if let Some(Tape::Record(recorder)) = &self.tape {
+            recorder
+                .record(Interaction {
+                    method: method.to_string(),
+                    endpoint: uri.to_string(),
+                    request_headers,
+                    request_body: body,
+                    status: status.as_u16(),
+                    response_body: response_body.clone(),
+                })
+                .await?;
         }
+
+        into_body(status, retry_after, response_body)
+    }
+}
+
+#[async_trait::async_trait]
+impl SkyscannerApi for Services {
+    async fn get_markets(&self, locale: &str) -> Result<Markets, SkyscannerError> {
+        let uri = format!("culture/markets/{locale}");
+
+        let body = self
+            .send(Method::GET, uri.as_str(), None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
     }
 
-    pub async fn get_locales(&self) -> Result<Locales, Error> {
+    async fn get_locales(&self) -> Result<Locales, SkyscannerError> {
         let uri = "culture/locales";
-        let url = self.get_url(uri);
 
-        let res = self.client.get(url.as_str()).send().await;
+        let body = self
+            .send(Method::GET, uri, None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
 
-        match res {
-            Ok(res) => res.json::<Locales>().await,
-            Err(e) => Err(e),
-        }
+    async fn get_currencies(&self) -> Result<Currencies, SkyscannerError> {
+        let uri = "culture/currencies";
+
+        let body = self
+            .send(Method::GET, uri, None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
     }
 
-    pub async fn create_a_request_to_find_flights(
+    async fn get_nearest_culture(
+        &self,
+        ip_address: Option<&str>,
+    ) -> Result<NearestCulture, SkyscannerError> {
+        let uri = match ip_address {
+            Some(ip_address) => format!("culture/nearestculture?ipAddress={ip_address}"),
+            None => "culture/nearestculture".to_string(),
+        };
+
+        let body = self
+            .send(Method::GET, &uri, None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
+
+    async fn create_a_request_to_find_flights(
         &self,
         q: &CreateFlightsRequest,
-    ) -> anyhow::Result<Option<FlightsResponse>> {
+    ) -> Result<FlightsResponse, SkyscannerError> {
         let uri = "flights/live/search/create";
-        let url = self.get_url(uri);
-
-        let res = self.client.post(url.as_str()).json(q).send().await?;
+        let q =
+            serde_json::to_value(q).map_err(|e| SkyscannerError::Configuration(e.to_string()))?;
 
-        Ok(res.text().await.map(|e| {
-            serde_json::from_str::<FlightsResponse>(e.as_str())
-                .map_err(|_| {
-                    dbg!(e);
-                })
-                .ok()
-        })?)
+        let body = self
+            .send(Method::POST, uri, Some(q), Idempotency::NotIdempotent)
+            .await?;
+        check_search(parse(body)?)
     }
 
-    pub async fn poll_a_request_to_find_flights(
+    async fn poll_a_request_to_find_flights(
         &self,
         token: &str,
-    ) -> Result<FlightsResponse, Error> {
+    ) -> Result<FlightsResponse, SkyscannerError> {
         let uri = format!("flights/live/search/poll/{token}");
-        let url = self.get_url(&uri);
 
-        let res = self.client.post(url.as_str()).send().await;
-        match res {
-            Ok(res) => res.json::<FlightsResponse>().await,
-            Err(e) => Err(e),
-        }
+        let body = self
+            .send(Method::POST, &uri, None, Idempotency::Idempotent)
+            .await?;
+        check_search(parse(body)?)
+    }
+
+    async fn create_itinerary_refresh(
+        &self,
+        session_token: &str,
+        q: &ItineraryRefreshRequest,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+        let uri = format!("flights/live/itineraryrefresh/create/{session_token}");
+        let q =
+            serde_json::to_value(q).map_err(|e| SkyscannerError::Configuration(e.to_string()))?;
+
+        let body = self
+            .send(Method::POST, &uri, Some(q), Idempotency::NotIdempotent)
+            .await?;
+        check_refresh(parse(body)?)
+    }
+
+    async fn poll_itinerary_refresh(
+        &self,
+        refresh_session_token: &str,
+    ) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+        let uri = format!("flights/live/itineraryrefresh/poll/{refresh_session_token}");
+
+        let body = self
+            .send(Method::GET, &uri, None, Idempotency::Idempotent)
+            .await?;
+        check_refresh(parse(body)?)
+    }
+
+    async fn search_indicative_prices(
+        &self,
+        q: &IndicativeSearchRequest,
+    ) -> Result<IndicativeResponse, SkyscannerError> {
+        let uri = "flights/indicative/search";
+        let q =
+            serde_json::to_value(q).map_err(|e| SkyscannerError::Configuration(e.to_string()))?;
+
+        let body = self
+            .send(Method::POST, uri, Some(q), Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
+
+    async fn autosuggest_flights(
+        &self,
+        q: &AutosuggestRequest,
+    ) -> Result<AutosuggestResponse, SkyscannerError> {
+        let uri = "autosuggest/flights";
+        let q =
+            serde_json::to_value(q).map_err(|e| SkyscannerError::Configuration(e.to_string()))?;
+
+        let body = self
+            .send(Method::POST, uri, Some(q), Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
+
+    async fn get_flight_locations(&self, locale: &str) -> Result<GeoResponse, SkyscannerError> {
+        let uri = format!("geo/hierarchy/flights/{locale}");
+
+        let body = self
+            .send(Method::GET, &uri, None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
+
+    async fn get_carriers(&self) -> Result<Carriers, SkyscannerError> {
+        let uri = "flights/carriers";
+
+        let body = self
+            .send(Method::GET, uri, None, Idempotency::Idempotent)
+            .await?;
+        parse(body)
+    }
+}
+
+fn into_body(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    body: String,
+) -> Result<String, SkyscannerError> {
+    if status.is_success() {
+        Ok(body)
+    } else {
+        Err(SkyscannerError::from_status(status, retry_after, body))
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: String) -> Result<T, SkyscannerError> {
+    serde_json::from_str::<T>(&body).map_err(|source| SkyscannerError::Deserialize { source, body })
+}
+
+fn check_search(res: FlightsResponse) -> Result<FlightsResponse, SkyscannerError> {
+    if res.status == ResponseStatus::ResultStatusFailed {
+        Err(SkyscannerError::SearchFailed {
+            session_token: res.session_token,
+        })
+    } else {
+        Ok(res)
+    }
+}
+
+fn check_refresh(
+    res: ItineraryRefreshResponse,
+) -> Result<ItineraryRefreshResponse, SkyscannerError> {
+    if res.status == ResponseStatus::ResultStatusFailed {
+        Err(SkyscannerError::SearchFailed {
+            session_token: res.refresh_session_token,
+        })
+    } else {
+        Ok(res)
     }
 }
@@ -1,5 +1,14 @@
+use std::cmp::Ordering;
+
 use chrono::{Datelike, Days, NaiveDate, Weekday};
 
+use crate::money::Money;
+
+/// The most days a `--depart` range may span, a year including a leap day.
+pub const MAX_DEPART_DAYS: usize = 366;
+/// The longest stay in days a `--stay` range may reach.
+pub const MAX_STAY_DAYS: u64 = 365;
+
 pub fn check_date_is_weekend(date: NaiveDate) -> bool {
     matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
 }
@@ -10,26 +19,116 @@ pub fn parse_date(year: i32, month: u16, day: u16) -> NaiveDate {
         .unwrap_or_else(|_| panic!("Failed to parse date {formatted_date}"))
 }
 
+/// One leg of a multi-city trip, from and to as typed by the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TripLeg {
+    pub from: String,
+    pub to: String,
+    pub date: NaiveDate,
+}
+
+/// Parses legs such as `TPE-NRT@2024-06-01,KIX-TPE@2024-06-08`, each one departing no earlier
+/// than the one before.
+pub fn parse_legs(s: &str) -> Result<Vec<TripLeg>, String> {
+    let mut legs: Vec<TripLeg> = vec![];
+    for (i, leg) in s.trim().split(',').map(str::trim).enumerate() {
+        let invalid = || format!("Invalid leg \"{leg}\", expected FROM-TO@YYYY-MM-DD");
+        let (route, date) = leg.rsplit_once('@').ok_or_else(invalid)?;
+        let (from, to) = route.split_once('-').ok_or_else(invalid)?;
+        let (from, to) = (from.trim(), to.trim());
+        let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").map_err(|_| invalid())?;
+        if from.is_empty() || to.is_empty() {
+            return Err(invalid());
+        }
+        if from.eq_ignore_ascii_case(to) {
+            return Err(format!("Leg {} flies from {from} to itself", i + 1));
+        }
+        if let Some(previous) = legs.last() {
+            if date < previous.date {
+                return Err(format!(
+                    "Leg {} departs on {date}, before leg {} on {}",
+                    i + 1,
+                    i,
+                    previous.date
+                ));
+            }
+        }
+        legs.push(TripLeg {
+            from: from.to_string(),
+            to: to.to_string(),
+            date,
+        });
+    }
+    Ok(legs)
+}
+
+/// Parses a range of days such as `2024-06-01..2024-06-15`, both included, or a single day.
+pub fn parse_date_range(s: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let parse = |e: &str| {
+        NaiveDate::parse_from_str(e.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date \"{}\", expected YYYY-MM-DD", e.trim()))
+    };
+    let (start, end) = match s.split_once("..") {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    if end < start {
+        return Err(format!("The range {start}..{end} ends before it starts"));
+    }
+    if (end - start).num_days() >= MAX_DEPART_DAYS as i64 {
+        return Err(format!(
+            "The range {start}..{end} spans more than {MAX_DEPART_DAYS} days"
+        ));
+    }
+    Ok((start, end))
+}
+
+/// Parses a range of stays in days such as `3..5`, both included, or a single stay.
+pub fn parse_stay_range(s: &str) -> Result<Vec<u64>, String> {
+    let parse = |e: &str| {
+        e.trim()
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid number of days \"{}\"", e.trim()))
+    };
+    let (min, max) = match s.split_once("..") {
+        Some((min, max)) => (parse(min)?, parse(max)?),
+        None => (parse(s)?, parse(s)?),
+    };
+    if max < min {
+        return Err(format!("The range {min}..{max} ends before it starts"));
+    }
+    if max > MAX_STAY_DAYS {
+        return Err(format!("A stay can't be longer than {MAX_STAY_DAYS} days"));
+    }
+    Ok((min..=max).collect())
+}
+
+/// Parses a weekday such as `fri` or `Friday`.
+pub fn parse_weekday(s: &str) -> Result<Weekday, String> {
+    s.trim()
+        .parse::<Weekday>()
+        .map_err(|_| format!("Invalid weekday \"{}\"", s.trim()))
+}
+
+/// Whether `date` falls on one of the weekdays, any day does when there are none.
+pub fn is_on_weekdays(date: NaiveDate, weekdays: &[Weekday]) -> bool {
+    weekdays.is_empty() || weekdays.contains(&date.weekday())
+}
+
 pub fn parse_input_days(s: &str) -> Result<Vec<Vec<u16>>, String> {
     Ok(s.trim()
         .split(':')
-        .into_iter()
         .map(|e| {
             e.trim()
                 .split(',')
-                .into_iter()
                 .filter_map(|ee| ee.parse::<u16>().ok())
                 .collect::<Vec<_>>()
         })
         .collect::<Vec<_>>())
 }
 
-pub fn create_dates(
-    year: i32,
-    months: Vec<u16>,
-    days: &[Vec<u16>],
-    durations: Vec<u64>,
-) -> Vec<(NaiveDate, NaiveDate)> {
+/// The departure days of every month, e.g. the 1st and 2nd of April.
+pub fn create_departure_dates(year: i32, months: Vec<u16>, days: &[Vec<u16>]) -> Vec<NaiveDate> {
     months
         .iter()
         .zip(days.iter())
@@ -38,6 +137,31 @@ pub fn create_dates(
                 .map(|d| parse_date(year, *m, *d))
                 .collect::<Vec<_>>()
         })
+        .collect::<Vec<_>>()
+}
+
+pub fn create_dates(
+    year: i32,
+    months: Vec<u16>,
+    days: &[Vec<u16>],
+    durations: Vec<u64>,
+) -> Vec<(NaiveDate, NaiveDate)> {
+    pair_dates(create_departure_dates(year, months, days), &durations)
+}
+
+/// Every day from `start` to `end`, both included, at most [`MAX_DEPART_DAYS`] of them.
+pub fn create_departure_range(start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+    start
+        .iter_days()
+        .take_while(|e| *e <= end)
+        .take(MAX_DEPART_DAYS)
+        .collect()
+}
+
+/// Pairs every departure day with the day coming back after each of the durations.
+pub fn pair_dates(departures: Vec<NaiveDate>, durations: &[u64]) -> Vec<(NaiveDate, NaiveDate)> {
+    departures
+        .into_iter()
         .flat_map(|from| {
             durations
                 .iter()
@@ -48,13 +172,90 @@ pub fn create_dates(
         .collect::<Vec<_>>()
 }
 
+/// Refuses to start more than `limit` live searches, e.g. every stay of up to a year after
+/// every day of a year would take days at the rate limit.
+pub fn check_trip_count(searches: usize, limit: usize) -> Result<(), String> {
+    if searches > limit {
+        return Err(format!(
+            "{searches} live searches are more than search.max_trips ({limit}), narrow the dates or pick the cheapest with --prescreen"
+        ));
+    }
+    Ok(())
+}
+
+/// Keeps the `limit` trips with the cheapest indicative quotes, cheapest first. A trip is a
+/// departure day and, unless it's one-way, a return day.
+///
+/// A trip is priced by the quotes departing on its first day, preferring quotes coming back
+/// on its return day, or one-way quotes for a one-way trip; trips without any quote are kept
+/// last in their original order.
+pub fn prescreen_dates(
+    dates: Vec<(NaiveDate, Option<NaiveDate>)>,
+    quotes: &[(NaiveDate, Option<NaiveDate>, Money)],
+    limit: usize,
+) -> Vec<(NaiveDate, Option<NaiveDate>)> {
+    // The quotes are all in the currency of the query, so they always compare.
+    let cheaper = |a: &&Money, b: &&Money| a.partial_cmp(b).unwrap_or(Ordering::Equal);
+    let mut priced = dates
+        .into_iter()
+        .map(|(from, to)| {
+            let departing = quotes.iter().filter(|(outbound, _, _)| *outbound == from);
+            let exact = departing
+                .clone()
+                .filter(|(_, inbound, _)| *inbound == to)
+                .map(|(_, _, price)| price)
+                .min_by(cheaper);
+            let price = exact.or_else(|| departing.map(|(_, _, price)| price).min_by(cheaper));
+            (price, (from, to))
+        })
+        .collect::<Vec<_>>();
+
+    priced.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => cheaper(a, b),
+        (a, b) => a.is_none().cmp(&b.is_none()),
+    });
+    priced
+        .into_iter()
+        .take(limit)
+        .map(|(_, dates)| dates)
+        .collect()
+}
+
+/// Edit distance between two strings, counted in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `input`, ignoring case, if it's at most two edits away and
+/// takes fewer edits than `input` has characters, e.g. `GB` is not a typo of `TW`.
+pub fn closest_match<'a>(input: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let input = input.to_lowercase();
+    let length = input.chars().count();
+    candidates
+        .iter()
+        .map(|candidate| (levenshtein(&input, &candidate.to_lowercase()), *candidate))
+        .filter(|(distance, _)| *distance <= 2 && *distance < length)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
     #[test]
     fn it_works_when_checking_the_date_is_weekend() {
-        let naive_dates = vec![
+        let naive_dates = [
             parse_date(2023, 3, 6),  // Mon
             parse_date(2023, 3, 7),  // Tue
             parse_date(2023, 3, 8),  // Wed
@@ -64,7 +265,7 @@ mod test {
             parse_date(2023, 3, 12), // Sun
         ];
 
-        let expected_values = vec![false, false, false, false, false, true, true];
+        let expected_values = [false, false, false, false, false, true, true];
         for (&naive_date, &expected_value) in naive_dates.iter().zip(expected_values.iter()) {
             assert_eq!(check_date_is_weekend(naive_date), expected_value);
         }
@@ -103,4 +304,146 @@ mod test {
         assert_eq!(res.len(), expected_values.len());
         assert_eq!(res, expected_values);
     }
+
+    #[test]
+    fn it_works_when_creating_dates_from_ranges() {
+        let (start, end) = parse_date_range("2023-12-30..2024-01-02").unwrap();
+        let departures = create_departure_range(start, end)
+            .into_iter()
+            .filter(|e| is_on_weekdays(*e, &[parse_weekday("sat").unwrap(), Weekday::Mon]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            departures,
+            vec![parse_date(2023, 12, 30), parse_date(2024, 1, 1)]
+        );
+
+        let stays = parse_stay_range("3..4").unwrap();
+        assert_eq!(stays, vec![3, 4]);
+        assert_eq!(
+            pair_dates(departures, &stays),
+            vec![
+                (parse_date(2023, 12, 30), parse_date(2024, 1, 2)),
+                (parse_date(2023, 12, 30), parse_date(2024, 1, 3)),
+                (parse_date(2024, 1, 1), parse_date(2024, 1, 4)),
+                (parse_date(2024, 1, 1), parse_date(2024, 1, 5)),
+            ]
+        );
+
+        assert_eq!(
+            parse_date_range("2024-06-01").unwrap(),
+            (parse_date(2024, 6, 1), parse_date(2024, 6, 1))
+        );
+        assert!(parse_date_range("2024-06-15..2024-06-01").is_err());
+        assert!(parse_date_range("2024-06-01..").is_err());
+        assert_eq!(parse_stay_range("5").unwrap(), vec![5]);
+        assert!(parse_stay_range("5..3").is_err());
+
+        // Ranges too long to search are rejected rather than expanded.
+        assert!(parse_date_range("2024-01-01..2024-12-31").is_ok());
+        assert!(parse_date_range("2024-01-01..2025-01-01").is_err());
+        assert_eq!(parse_stay_range("365").unwrap(), vec![365]);
+        assert!(parse_stay_range("0..18446744073709551615").is_err());
+        assert_eq!(
+            create_departure_range(parse_date(2024, 1, 1), parse_date(2030, 1, 1)).len(),
+            MAX_DEPART_DAYS
+        );
+        assert_eq!(parse_weekday("Friday").unwrap(), Weekday::Fri);
+        assert!(parse_weekday("someday").is_err());
+        assert!(is_on_weekdays(parse_date(2024, 6, 4), &[]));
+    }
+
+    #[test]
+    fn it_works_when_checking_the_trip_count() {
+        assert!(check_trip_count(200, 200).is_ok());
+        assert!(check_trip_count(201, 200).is_err());
+
+        // Both ranges are within their caps, their product isn't.
+        let (start, end) = parse_date_range("2024-01-01..2024-12-31").unwrap();
+        let stays = parse_stay_range("0..365").unwrap();
+        let trips = pair_dates(create_departure_range(start, end), &stays);
+        assert!(check_trip_count(trips.len(), 200).is_err());
+    }
+
+    #[test]
+    fn it_works_when_parsing_legs() {
+        let legs = parse_legs("TPE-NRT@2024-06-01, KIX-TPE@2024-06-08").unwrap();
+        assert_eq!(
+            legs,
+            vec![
+                TripLeg {
+                    from: "TPE".to_string(),
+                    to: "NRT".to_string(),
+                    date: parse_date(2024, 6, 1),
+                },
+                TripLeg {
+                    from: "KIX".to_string(),
+                    to: "TPE".to_string(),
+                    date: parse_date(2024, 6, 8),
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_legs("TPE-NRT@2024-06-08,KIX-TPE@2024-06-01").unwrap_err(),
+            "Leg 2 departs on 2024-06-01, before leg 1 on 2024-06-08"
+        );
+        assert!(parse_legs("TPE-TPE@2024-06-01").is_err());
+        assert!(parse_legs("TPE-NRT").is_err());
+        assert!(parse_legs("TPE@2024-06-01").is_err());
+        assert!(parse_legs("TPE-NRT@2024-13-01").is_err());
+    }
+
+    #[test]
+    fn it_works_when_prescreening_dates() {
+        let dates = vec![
+            (parse_date(2023, 4, 1), Some(parse_date(2023, 4, 4))),
+            (parse_date(2023, 4, 2), Some(parse_date(2023, 4, 5))),
+            (parse_date(2023, 4, 3), Some(parse_date(2023, 4, 6))),
+            (parse_date(2023, 4, 9), Some(parse_date(2023, 4, 12))),
+        ];
+        let quotes = vec![
+            (
+                parse_date(2023, 4, 1),
+                Some(parse_date(2023, 4, 4)),
+                Money::from_milli(5000, "TWD"),
+            ),
+            (
+                parse_date(2023, 4, 1),
+                Some(parse_date(2023, 4, 8)),
+                Money::from_milli(1000, "TWD"),
+            ),
+            (parse_date(2023, 4, 2), None, Money::from_milli(3000, "TWD")),
+            (
+                parse_date(2023, 4, 3),
+                Some(parse_date(2023, 4, 6)),
+                Money::from_milli(4000, "TWD"),
+            ),
+        ];
+
+        let res = prescreen_dates(dates.clone(), &quotes, 3);
+        assert_eq!(res, vec![dates[1], dates[2], dates[0]]);
+
+        let res = prescreen_dates(dates.clone(), &[], 2);
+        assert_eq!(res, vec![dates[0], dates[1]]);
+
+        let one_way = vec![
+            (parse_date(2023, 4, 3), None),
+            (parse_date(2023, 4, 2), None),
+        ];
+        let res = prescreen_dates(one_way.clone(), &quotes, 2);
+        assert_eq!(res, vec![one_way[1], one_way[0]]);
+    }
+
+    #[test]
+    fn it_works_when_suggesting_the_closest_match() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "TWD"), 3);
+        assert_eq!(levenshtein("TWD", "TWD"), 0);
+
+        let codes = ["TWD", "HKD", "JPY", "USD"];
+        assert_eq!(closest_match("TDW", &codes), Some("TWD"));
+        assert_eq!(closest_match("jpn", &codes), Some("JPY"));
+        assert_eq!(closest_match("EUR", &codes), None);
+        assert_eq!(closest_match("GB", &["TW"]), None);
+    }
 }